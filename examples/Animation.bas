@@ -0,0 +1,25 @@
+10 SCREEN 640 , 360
+20 LET X = 40
+30 LET Y = 40
+40 LET DX = 3
+50 LET DY = 2
+60 LET R = 20
+
+70 DO
+80 COLOR 0 , 0 , 0
+90 CLEAR
+100 COLOR 255 , X * 255 / 640 , Y * 255 / 360
+110 CIRCLE X , Y , R
+120 LINE X - R , Y TO X + R , Y
+130 LINE X , Y - R TO X , Y + R
+140 DOT X + R / 2 , Y - R / 2
+
+150 LET X = X + DX
+160 LET Y = Y + DY
+170 IF X - R < 0 THEN LET DX = -DX
+180 IF X + R > 640 THEN LET DX = -DX
+190 IF Y - R < 0 THEN LET DY = -DY
+200 IF Y + R > 360 THEN LET DY = -DY
+203 FLIP
+206 WAIT FRAME
+210 LOOP
@@ -4,4 +4,4 @@
 40 PRINT "What is the second number? "
 50 INPUT Y
 60 PRINT "Their sum is: "
-80 PRINT X + Y
+80 PRINT VAL(X) + VAL(Y)
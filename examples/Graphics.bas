@@ -2,6 +2,7 @@
 20 COLOR 0 , 0 , 0
 30 CLEAR
 40 COLOR 255 , 255 , 255
+45 DO
 
 50  LINE 320 , 170 TO 600 , 170
 60  LINE 320 , 450 TO 600 , 450
@@ -33,5 +34,7 @@
 270 DOT 1016 , 180
 280 DOT 1167 , 362
 290 DOT 1259 , 551
+293 FLIP
+296 WAIT FRAME
 
-300 GOTO 50
+300 LOOP
@@ -2,14 +2,38 @@ if exists("b:current_syntax")
 	finish
 endif
 
+syntax case ignore
+
 syntax keyword basKeyword
 	\ PRINT
 	\ INPUT
 	\ LET
+	\ DIM
+	\ OPTION
+	\ BASE
 	\ IF
 	\ THEN
+	\ ELSE
+	\ ELSEIF
 	\ GOTO
+	\ GOSUB
+	\ DEF
+	\ SUB
+	\ FUNCTION
+	\ CALL
+	\ SHARED
+	\ EXIT
+	\ RETURN
 	\ END
+	\ RANDOMIZE
+	\ FOR
+	\ NEXT
+	\ STEP
+	\ WHILE
+	\ WEND
+	\ DO
+	\ LOOP
+	\ UNTIL
 	\ SCREEN
 	\ CLEAR
 	\ COLOR
@@ -17,22 +41,54 @@ syntax keyword basKeyword
 	\ TO
 	\ DOT
 	\ CIRCLE
+	\ BOX
+	\ RECT
+	\ ELLIPSE
+	\ POLYGON
+	\ PAINT
+	\ FILL
+	\ SAVEIMAGE
+	\ FLIP
+	\ PRESENT
+	\ SLEEP
+	\ WAIT
+	\ FRAME
+	\ AND
+	\ OR
+	\ XOR
+	\ NOT
+	\ MOD
+
+syntax keyword basFunction
+	\ SIN COS TAN ASN ACS ATN
+	\ SQR EXP LOG
+	\ ABS SGN INT FIX
+	\ MIN MAX RND PI
+	\ LEN INSTR ASC VAL
+syntax match basFunction "\v<(LEFT|RIGHT|MID|CHR|STR|UCASE|LCASE|TRIM|SPACE)\$"
 
-syntax match  basNumber "\v<\d+>"
-syntax region basString start=/"/ end=/"/
+syntax match  basNumber "\v(<\d+\.?\d*|\.\d+)([eE][-+]?\d+)?>"
+syntax region basString start=/"/ skip=/""/ end=/"/
 syntax match  basComment "\vREM.*$"
+syntax match  basComment "\v'.*$"
+syntax match  basLabel   "\v^\s*(\d+\s+)?\zs\a\w*:"
 
 syntax match basOperator "\v\+"
 syntax match basOperator "\v\-"
 syntax match basOperator "\v\*"
 syntax match basOperator "\v\/"
 syntax match basOperator "\v\!"
+syntax match basOperator "\v\^"
+syntax match basOperator "\v\\\\"
 syntax match basOperator "\v\,"
+syntax match basOperator "\v\:"
 
 hi default link basNumber   Number
 hi default link basString   String
 hi default link basComment  Comment
+hi default link basLabel    Label
 hi default link basKeyword  Keyword
+hi default link basFunction Function
 hi default link basOperator Operator
 
 let b:current_syntax = "bas"
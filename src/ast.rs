@@ -0,0 +1,150 @@
+use crate::error::Span;
+use crate::token;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Text(String),
+    Variable(String),
+    // Calls and binary operators keep the column of their name or operator for runtime errors.
+    Call(String, Vec<Expr>, Span),
+    Unary(token::Token, Box<Expr>),
+    Binary(token::Token, Box<Expr>, Box<Expr>, Span),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoopCondition {
+    While(Expr),
+    Until(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Print(Expr),
+    Input(String),
+    Let {
+        name: String,
+        subscripts: Option<Vec<Expr>>,
+        value: Expr,
+    },
+    Dim(Vec<(String, Vec<Expr>)>),
+    OptionBase(usize),
+    Rem(String),
+    End,
+
+    If {
+        condition: Expr,
+        then_branch: Vec<Stmt>,
+        else_branch: Option<Vec<Stmt>>,
+    },
+    IfBlock(Expr),
+    ElseIf(Expr),
+    Else,
+    EndIf,
+    Goto(u32),
+    Gosub(u32),
+    Return,
+    For {
+        variable: String,
+        start: Expr,
+        limit: Expr,
+        step: Option<Expr>,
+    },
+    Next(Option<String>),
+    While(Expr),
+    Wend,
+    Do(Option<LoopCondition>),
+    Loop(Option<LoopCondition>),
+    ExitDo,
+    ExitFor,
+    Randomize(Option<Expr>),
+
+    Def {
+        name: String,
+        parameters: Vec<String>,
+        body: Expr,
+    },
+    Sub {
+        name: String,
+        parameters: Vec<String>,
+    },
+    Function {
+        name: String,
+        parameters: Vec<String>,
+    },
+    EndSub,
+    EndFunction,
+    ExitSub,
+    ExitFunction,
+    Call {
+        name: String,
+        arguments: Vec<Expr>,
+    },
+    Shared(Vec<String>),
+
+    Screen {
+        width: Expr,
+        height: Expr,
+    },
+    Clear,
+    Color {
+        red: Expr,
+        green: Expr,
+        blue: Expr,
+    },
+    Dot {
+        x: Expr,
+        y: Expr,
+    },
+    Line {
+        x1: Expr,
+        y1: Expr,
+        x2: Expr,
+        y2: Expr,
+    },
+    Circle {
+        x: Expr,
+        y: Expr,
+        radius: Expr,
+        arc: Option<(Expr, Expr)>,
+        fill: bool,
+    },
+    Box {
+        x1: Expr,
+        y1: Expr,
+        x2: Expr,
+        y2: Expr,
+        fill: bool,
+    },
+    Ellipse {
+        x: Expr,
+        y: Expr,
+        radius_x: Expr,
+        radius_y: Expr,
+        fill: bool,
+    },
+    Polygon {
+        points: Vec<(Expr, Expr)>,
+        fill: bool,
+    },
+    Paint {
+        x: Expr,
+        y: Expr,
+    },
+    Flip,
+    Sleep(Expr),
+    WaitFrame,
+    SaveImage(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub line_number: u32,
+    pub position: u32,
+    pub statement: Stmt,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    pub lines: Vec<Line>,
+}
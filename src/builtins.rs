@@ -0,0 +1,333 @@
+use crate::error::{ErrorCode, SbasicError};
+use crate::value_type::ValueType;
+
+use std::cell::Cell;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_SEED: u64 = 0x853c_49e6_748f_ea9b;
+pub const MAX_SPACES: usize = 1 << 22;
+
+// (name, minimum arguments, maximum arguments)
+const BUILTINS: [(&str, usize, usize); 30] = [
+    ("SIN", 1, 1),
+    ("COS", 1, 1),
+    ("TAN", 1, 1),
+    ("ASN", 1, 1),
+    ("ACS", 1, 1),
+    ("ATN", 1, 1),
+    ("SQR", 1, 1),
+    ("ABS", 1, 1),
+    ("SGN", 1, 1),
+    ("INT", 1, 1),
+    ("FIX", 1, 1),
+    ("EXP", 1, 1),
+    ("LOG", 1, 1),
+    ("MIN", 1, usize::MAX),
+    ("MAX", 1, usize::MAX),
+    ("RND", 0, 1),
+    ("PI", 0, 0),
+    ("LEN", 1, 1),
+    ("LEFT$", 2, 2),
+    ("RIGHT$", 2, 2),
+    ("MID$", 2, 3),
+    ("INSTR", 2, 3),
+    ("CHR$", 1, 1),
+    ("ASC", 1, 1),
+    ("STR$", 1, 1),
+    ("VAL", 1, 1),
+    ("UCASE$", 1, 1),
+    ("LCASE$", 1, 1),
+    ("TRIM$", 1, 1),
+    ("SPACE$", 1, 1),
+];
+
+thread_local! {
+    static RANDOM_STATE: Cell<u64> = const { Cell::new(DEFAULT_SEED) };
+    static LAST_RANDOM: Cell<f64> = const { Cell::new(0.0) };
+}
+
+fn arity(name: &str) -> Option<(usize, usize)> {
+    BUILTINS
+        .iter()
+        .find(|&&(builtin, _, _)| builtin == name)
+        .map(|&(_, minimum, maximum)| (minimum, maximum))
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    arity(name).is_some()
+}
+
+pub fn accepts_no_arguments(name: &str) -> bool {
+    matches!(arity(name), Some((0, _)))
+}
+
+pub fn seed(seed: f64) {
+    // splitmix64 so that nearby seeds still produce unrelated sequences.
+    let mut state = seed.to_bits().wrapping_add(0x9e37_79b9_7f4a_7c15);
+    state = (state ^ (state >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    state = (state ^ (state >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    state ^= state >> 31;
+
+    RANDOM_STATE
+        .with(|random_state| random_state.set(if state == 0 { DEFAULT_SEED } else { state }));
+}
+
+pub fn seed_from_time() {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0);
+    seed(nanos as f64);
+}
+
+fn next_random() -> f64 {
+    let value = RANDOM_STATE.with(|random_state| {
+        let mut state = random_state.get();
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        random_state.set(state);
+
+        (state >> 11) as f64 / (1u64 << 53) as f64
+    });
+
+    LAST_RANDOM.with(|last_random| last_random.set(value));
+    value
+}
+
+fn to_number(name: &str, value: &ValueType) -> Result<f64, SbasicError> {
+    match value {
+        ValueType::Number(number) => Ok(*number),
+        ValueType::Text(text) => match f64::from_str(text.trim()) {
+            Ok(number) => Ok(number),
+            Err(_) => Err(SbasicError::type_mismatch(format!(
+                "{} expects a number, got string: {}.",
+                name, text
+            ))),
+        },
+        _ => Err(SbasicError::type_mismatch(format!(
+            "{} expects a number.",
+            name
+        ))),
+    }
+}
+
+fn to_text<'a>(name: &str, value: &'a ValueType) -> Result<&'a str, SbasicError> {
+    match value {
+        ValueType::Text(text) => Ok(text.as_str()),
+        _ => Err(SbasicError::type_mismatch(format!(
+            "{} expects a string.",
+            name
+        ))),
+    }
+}
+
+fn to_count(name: &str, value: &ValueType) -> Result<usize, SbasicError> {
+    let number = to_number(name, value)?;
+
+    if number < 0.0 {
+        Err(SbasicError::runtime(
+            ErrorCode::IllegalFunctionCall,
+            format!("Illegal function call: {} with {}.", name, number),
+        ))
+    } else {
+        Ok(number as usize)
+    }
+}
+
+fn illegal_call(name: &str, argument: f64) -> Result<ValueType, SbasicError> {
+    Err(SbasicError::runtime(
+        ErrorCode::IllegalFunctionCall,
+        format!(
+            "Illegal function call: {}({}).",
+            name,
+            ValueType::Number(argument)
+        ),
+    ))
+}
+
+fn parse_number_prefix(text: &str) -> f64 {
+    let text = text.trim_start();
+    let numeric_length = text
+        .find(|character: char| !matches!(character, '0'..='9' | '.' | 'e' | 'E' | '+' | '-'))
+        .unwrap_or(text.len());
+
+    (0..=numeric_length)
+        .rev()
+        .find_map(|length| f64::from_str(&text[..length]).ok())
+        .unwrap_or(0.0)
+}
+
+fn call_text(name: &str, arguments: &[ValueType]) -> Result<ValueType, SbasicError> {
+    let result = match name {
+        "LEN" => {
+            return Ok(ValueType::Number(
+                to_text(name, &arguments[0])?.chars().count() as f64,
+            ))
+        }
+        "LEFT$" => {
+            let text = to_text(name, &arguments[0])?;
+            text.chars().take(to_count(name, &arguments[1])?).collect()
+        }
+        "RIGHT$" => {
+            let text = to_text(name, &arguments[0])?;
+            let length = to_count(name, &arguments[1])?;
+            let skip = text.chars().count().saturating_sub(length);
+            text.chars().skip(skip).collect()
+        }
+        "MID$" => {
+            let text = to_text(name, &arguments[0])?;
+            let start = to_count(name, &arguments[1])?;
+            if start == 0 {
+                return illegal_call(name, 0.0);
+            }
+
+            let length = match arguments.get(2) {
+                Some(length) => to_count(name, length)?,
+                None => usize::MAX,
+            };
+            text.chars().skip(start - 1).take(length).collect()
+        }
+        "INSTR" => {
+            let (start, haystack, needle) = match arguments.len() {
+                3 => (
+                    to_count(name, &arguments[0])?,
+                    to_text(name, &arguments[1])?,
+                    to_text(name, &arguments[2])?,
+                ),
+                _ => (
+                    1,
+                    to_text(name, &arguments[0])?,
+                    to_text(name, &arguments[1])?,
+                ),
+            };
+            if start == 0 {
+                return illegal_call(name, 0.0);
+            }
+
+            let characters: Vec<char> = haystack.chars().collect();
+            let needle: Vec<char> = needle.chars().collect();
+            let position = if needle.len() > characters.len() {
+                0
+            } else {
+                (start - 1..=characters.len() - needle.len())
+                    .find(|&index| characters[index..index + needle.len()] == needle[..])
+                    .map(|index| index + 1)
+                    .unwrap_or(0)
+            };
+
+            return Ok(ValueType::Number(position as f64));
+        }
+        "CHR$" => {
+            let code = to_number(name, &arguments[0])?;
+            match char::from_u32(code as u32) {
+                Some(character) if code >= 0.0 => character.to_string(),
+                _ => return illegal_call(name, code),
+            }
+        }
+        "ASC" => match to_text(name, &arguments[0])?.chars().next() {
+            Some(character) => return Ok(ValueType::Number(character as u32 as f64)),
+            None => {
+                return Err(SbasicError::runtime(
+                    ErrorCode::IllegalFunctionCall,
+                    "Illegal function call: ASC of an empty string.",
+                ))
+            }
+        },
+        "STR$" => match arguments[0] {
+            ValueType::Number(_) => arguments[0].to_string(),
+            _ => {
+                return Err(SbasicError::type_mismatch(format!(
+                    "{} expects a number.",
+                    name
+                )))
+            }
+        },
+        "VAL" => {
+            return Ok(ValueType::Number(parse_number_prefix(to_text(
+                name,
+                &arguments[0],
+            )?)))
+        }
+        "UCASE$" => to_text(name, &arguments[0])?.to_uppercase(),
+        "LCASE$" => to_text(name, &arguments[0])?.to_lowercase(),
+        "TRIM$" => to_text(name, &arguments[0])?.trim().to_string(),
+        "SPACE$" => match to_count(name, &arguments[0])? {
+            length if length <= MAX_SPACES => " ".repeat(length),
+            length => return illegal_call(name, length as f64),
+        },
+
+        _ => unreachable!(),
+    };
+
+    Ok(ValueType::Text(result))
+}
+
+fn call_number(name: &str, arguments: &[ValueType]) -> Result<ValueType, SbasicError> {
+    let mut numbers: Vec<f64> = Vec::new();
+    for argument in arguments.iter() {
+        numbers.push(to_number(name, argument)?);
+    }
+
+    let result = match name {
+        "SIN" => numbers[0].sin(),
+        "COS" => numbers[0].cos(),
+        "TAN" => numbers[0].tan(),
+        "ASN" if numbers[0].abs() > 1.0 => return illegal_call(name, numbers[0]),
+        "ASN" => numbers[0].asin(),
+        "ACS" if numbers[0].abs() > 1.0 => return illegal_call(name, numbers[0]),
+        "ACS" => numbers[0].acos(),
+        "ATN" => numbers[0].atan(),
+        "SQR" if numbers[0] < 0.0 => return illegal_call(name, numbers[0]),
+        "SQR" => numbers[0].sqrt(),
+        "ABS" => numbers[0].abs(),
+        "SGN" if numbers[0] == 0.0 => 0.0,
+        "SGN" => numbers[0].signum(),
+        "INT" => numbers[0].floor(),
+        "FIX" => numbers[0].trunc(),
+        "EXP" => numbers[0].exp(),
+        "LOG" if numbers[0] <= 0.0 => return illegal_call(name, numbers[0]),
+        "LOG" => numbers[0].ln(),
+        "MIN" => numbers.iter().cloned().fold(f64::INFINITY, f64::min),
+        "MAX" => numbers.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        "RND" => match numbers.first() {
+            Some(&0.0) => LAST_RANDOM.with(|last_random| last_random.get()),
+            Some(&argument) if argument < 0.0 => {
+                seed(argument);
+                next_random()
+            }
+            _ => next_random(),
+        },
+        "PI" => std::f64::consts::PI,
+
+        _ => unreachable!(),
+    };
+
+    Ok(ValueType::Number(result))
+}
+
+pub fn call(name: &str, arguments: Vec<ValueType>) -> Result<ValueType, SbasicError> {
+    let (minimum, maximum) = match arity(name) {
+        Some(arity) => arity,
+        None => {
+            return Err(SbasicError::runtime(
+                ErrorCode::UndefinedFunction,
+                format!("Undefined function {}.", name),
+            ))
+        }
+    };
+
+    if arguments.len() < minimum || arguments.len() > maximum {
+        return Err(SbasicError::runtime(
+            ErrorCode::ArgumentCount,
+            format!("{} called with {} argument(s).", name, arguments.len()),
+        ));
+    }
+
+    match name {
+        "LEN" | "LEFT$" | "RIGHT$" | "MID$" | "INSTR" | "CHR$" | "ASC" | "STR$" | "VAL"
+        | "UCASE$" | "LCASE$" | "TRIM$" | "SPACE$" => call_text(name, &arguments),
+        _ => call_number(name, &arguments),
+    }
+}
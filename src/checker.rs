@@ -0,0 +1,387 @@
+use crate::ast;
+use crate::error::{ErrorCode, SbasicError, Span};
+use crate::interpreter::Layout;
+
+use std::collections::{HashSet, VecDeque};
+
+#[derive(Default)]
+pub struct Report {
+    pub errors: Vec<SbasicError>,
+    pub warnings: Vec<SbasicError>,
+}
+
+fn locate(error: SbasicError, line: &ast::Line) -> SbasicError {
+    error.at(line.line_number, Span::at(line.position))
+}
+
+fn jump_targets(statement: &ast::Stmt, targets: &mut Vec<u32>) {
+    match statement {
+        ast::Stmt::Goto(target) | ast::Stmt::Gosub(target) => targets.push(*target),
+        ast::Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            for statement in then_branch.iter().chain(else_branch.iter().flatten()) {
+                jump_targets(statement, targets);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn expression_reads<'a>(expression: &'a ast::Expr, reads: &mut Vec<&'a str>) {
+    match expression {
+        ast::Expr::Number(_) | ast::Expr::Text(_) => {}
+        ast::Expr::Variable(name) => reads.push(name),
+        ast::Expr::Call(_, arguments, _) => {
+            for argument in arguments.iter() {
+                expression_reads(argument, reads);
+            }
+        }
+        ast::Expr::Unary(_, operand) => expression_reads(operand, reads),
+        ast::Expr::Binary(_, left, right, _) => {
+            expression_reads(left, reads);
+            expression_reads(right, reads);
+        }
+    }
+}
+
+// DEF FN bodies are left out, their parameters are only bound when the function is called.
+fn statement_accesses<'a>(
+    statement: &'a ast::Stmt,
+    reads: &mut Vec<&'a str>,
+    writes: &mut Vec<&'a str>,
+) {
+    let mut read = |expressions: &[&'a ast::Expr]| {
+        for expression in expressions.iter() {
+            expression_reads(expression, reads);
+        }
+    };
+
+    match statement {
+        ast::Stmt::Print(value) => read(&[value]),
+        ast::Stmt::Input(name) => writes.push(name),
+        ast::Stmt::Let {
+            name,
+            subscripts,
+            value,
+        } => {
+            if let Some(subscripts) = subscripts {
+                read(&subscripts.iter().collect::<Vec<_>>());
+            }
+            read(&[value]);
+            writes.push(name);
+        }
+        ast::Stmt::Dim(arrays) => {
+            for (name, bounds) in arrays.iter() {
+                read(&bounds.iter().collect::<Vec<_>>());
+                writes.push(name);
+            }
+        }
+        ast::Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            read(&[condition]);
+            for statement in then_branch.iter().chain(else_branch.iter().flatten()) {
+                statement_accesses(statement, reads, writes);
+            }
+        }
+        ast::Stmt::IfBlock(condition)
+        | ast::Stmt::ElseIf(condition)
+        | ast::Stmt::While(condition)
+        | ast::Stmt::Do(Some(ast::LoopCondition::While(condition)))
+        | ast::Stmt::Do(Some(ast::LoopCondition::Until(condition)))
+        | ast::Stmt::Loop(Some(ast::LoopCondition::While(condition)))
+        | ast::Stmt::Loop(Some(ast::LoopCondition::Until(condition))) => read(&[condition]),
+        ast::Stmt::For {
+            variable,
+            start,
+            limit,
+            step,
+        } => {
+            read(&[start, limit]);
+            if let Some(step) = step {
+                read(&[step]);
+            }
+            writes.push(variable);
+        }
+        ast::Stmt::Randomize(Some(seed)) => read(&[seed]),
+        ast::Stmt::Call { arguments, .. } => read(&arguments.iter().collect::<Vec<_>>()),
+        ast::Stmt::Shared(names) => writes.extend(names.iter().map(|name| name.as_str())),
+        ast::Stmt::Screen { width, height } => read(&[width, height]),
+        ast::Stmt::Color { red, green, blue } => read(&[red, green, blue]),
+        ast::Stmt::Dot { x, y } => read(&[x, y]),
+        ast::Stmt::Line { x1, y1, x2, y2 } => read(&[x1, y1, x2, y2]),
+        ast::Stmt::Circle {
+            x, y, radius, arc, ..
+        } => {
+            read(&[x, y, radius]);
+            if let Some((start, end)) = arc {
+                read(&[start, end]);
+            }
+        }
+        ast::Stmt::Box { x1, y1, x2, y2, .. } => read(&[x1, y1, x2, y2]),
+        ast::Stmt::Ellipse {
+            x,
+            y,
+            radius_x,
+            radius_y,
+            ..
+        } => read(&[x, y, radius_x, radius_y]),
+        ast::Stmt::Polygon { points, .. } => {
+            read(&points.iter().flat_map(|(x, y)| [x, y]).collect::<Vec<_>>())
+        }
+        ast::Stmt::Paint { x, y } => read(&[x, y]),
+        ast::Stmt::Sleep(milliseconds) => read(&[milliseconds]),
+        ast::Stmt::SaveImage(path) => read(&[path]),
+        _ => {}
+    }
+}
+
+fn has_end(statement: &ast::Stmt) -> bool {
+    match statement {
+        ast::Stmt::End => true,
+        ast::Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => then_branch
+            .iter()
+            .chain(else_branch.iter().flatten())
+            .any(has_end),
+        _ => false,
+    }
+}
+
+struct Checker<'a> {
+    layout: Layout<'a>,
+    report: Report,
+}
+
+impl<'a> Checker<'a> {
+    fn check_targets(&mut self) {
+        for line in self.layout.lines.iter() {
+            let mut targets: Vec<u32> = Vec::new();
+            jump_targets(&line.statement, &mut targets);
+
+            for target in targets {
+                if !self.layout.instruction_map.contains_key(&target) {
+                    let error = SbasicError::parse(
+                        ErrorCode::InvalidTarget,
+                        format!("Line {} does not exist.", target),
+                    );
+                    self.report.errors.push(locate(error, line));
+                }
+            }
+        }
+    }
+
+    fn successors(&self, index: usize, statement: &ast::Stmt, next: &mut Vec<usize>) {
+        let layout = &self.layout;
+        let after = |start: usize| layout.loop_targets.get(&start).map(|end| end + 1);
+
+        match statement {
+            ast::Stmt::Goto(target) => next.extend(layout.instruction_map.get(target)),
+            ast::Stmt::Gosub(target) => {
+                next.extend(layout.instruction_map.get(target));
+                next.push(index + 1);
+            }
+            ast::Stmt::End
+            | ast::Stmt::Return
+            | ast::Stmt::EndSub
+            | ast::Stmt::EndFunction
+            | ast::Stmt::ExitSub
+            | ast::Stmt::ExitFunction => {}
+
+            ast::Stmt::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                // Each statement of a branch counts on its own, which can only add successors.
+                for statement in then_branch.iter().chain(else_branch.iter().flatten()) {
+                    self.successors(index, statement, next);
+                }
+                if else_branch.is_none() {
+                    next.push(index + 1);
+                }
+            }
+            ast::Stmt::IfBlock(_) | ast::Stmt::ElseIf(_) | ast::Stmt::Else => {
+                next.push(index + 1);
+                if let Some(clause) = layout.if_clauses.get(&index) {
+                    if *statement != ast::Stmt::Else {
+                        next.push(clause.next_clause);
+                    }
+                    next.push(clause.end_if + 1);
+                }
+            }
+
+            ast::Stmt::Do(None) => next.push(index + 1),
+            ast::Stmt::While(_) | ast::Stmt::Do(_) | ast::Stmt::For { .. } => {
+                next.push(index + 1);
+                next.extend(after(index));
+            }
+            ast::Stmt::Wend | ast::Stmt::Loop(None) => next.extend(layout.loop_targets.get(&index)),
+            ast::Stmt::Loop(_) => {
+                next.extend(layout.loop_targets.get(&index));
+                next.push(index + 1);
+            }
+            ast::Stmt::ExitDo => {
+                next.extend(layout.do_exits.get(&index).and_then(|&start| after(start)))
+            }
+            ast::Stmt::ExitFor => {
+                next.extend(layout.for_exits.get(&index).and_then(|&start| after(start)))
+            }
+
+            ast::Stmt::Sub { name, .. } | ast::Stmt::Function { name, .. } => {
+                next.extend(
+                    layout
+                        .procedures
+                        .get(name.as_str())
+                        .map(|procedure| procedure.end_index + 1),
+                );
+            }
+            _ => next.push(index + 1),
+        }
+    }
+
+    // Every branch is assumed to be taken, so only lines that no path can reach are reported.
+    fn check_reachability(&mut self) {
+        let lines = self.layout.lines;
+        let mut reached = vec![false; lines.len()];
+        let mut queue: VecDeque<usize> = VecDeque::new();
+
+        queue.push_back(0);
+        for procedure in self.layout.procedures.values() {
+            queue.push_back(procedure.start_index + 1);
+        }
+
+        while let Some(index) = queue.pop_front() {
+            if index >= lines.len() || reached[index] {
+                continue;
+            }
+            reached[index] = true;
+
+            let mut next: Vec<usize> = Vec::new();
+            self.successors(index, &lines[index].statement, &mut next);
+            queue.extend(next);
+        }
+
+        let mut index = 0;
+        while index < lines.len() {
+            let is_reported = |index: usize| {
+                !reached[index]
+                    && !matches!(
+                        lines[index].statement,
+                        ast::Stmt::Rem(_)
+                            | ast::Stmt::EndIf
+                            | ast::Stmt::Sub { .. }
+                            | ast::Stmt::Function { .. }
+                            | ast::Stmt::EndSub
+                            | ast::Stmt::EndFunction
+                    )
+            };
+            if !is_reported(index) {
+                index += 1;
+                continue;
+            }
+
+            let start = index;
+            while index + 1 < lines.len() && !reached[index + 1] {
+                index += 1;
+            }
+            let message = if index == start {
+                format!("Line {} can never be reached.", lines[start].line_number)
+            } else {
+                format!(
+                    "Lines {} to {} can never be reached.",
+                    lines[start].line_number, lines[index].line_number
+                )
+            };
+            let warning = SbasicError::warning(ErrorCode::UnreachableLine, message);
+            self.report.warnings.push(locate(warning, &lines[start]));
+            index += 1;
+        }
+    }
+
+    // Lines are checked in source order, SUB and FUNCTION bodies with their own variables.
+    fn check_assignments(&mut self) {
+        let mut globals: HashSet<&str> = HashSet::new();
+        let mut locals: Option<HashSet<&str>> = None;
+        let mut reported: HashSet<&str> = HashSet::new();
+
+        for line in self.layout.lines.iter() {
+            match &line.statement {
+                ast::Stmt::Sub { parameters, .. } => {
+                    locals = Some(parameters.iter().map(|name| name.as_str()).collect());
+                    continue;
+                }
+                ast::Stmt::Function { name, parameters } => {
+                    let mut names: HashSet<&str> =
+                        parameters.iter().map(|name| name.as_str()).collect();
+                    names.insert(name);
+                    locals = Some(names);
+                    continue;
+                }
+                ast::Stmt::EndSub | ast::Stmt::EndFunction => {
+                    locals = None;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let mut reads: Vec<&str> = Vec::new();
+            let mut writes: Vec<&str> = Vec::new();
+            statement_accesses(&line.statement, &mut reads, &mut writes);
+
+            let assigned = locals.as_mut().unwrap_or(&mut globals);
+            for name in reads {
+                if !assigned.contains(name) && reported.insert(name) {
+                    let warning = SbasicError::warning(
+                        ErrorCode::ReadBeforeAssignment,
+                        format!("{} is read before it is assigned.", name),
+                    );
+                    self.report.warnings.push(locate(warning, line));
+                }
+            }
+            assigned.extend(writes);
+        }
+    }
+
+    fn check_end(&mut self) {
+        let lines = self.layout.lines;
+        if lines.iter().any(|line| has_end(&line.statement)) {
+            return;
+        }
+
+        if let Some(last) = lines.last() {
+            let warning = SbasicError::warning(ErrorCode::MissingEnd, "Program has no END.");
+            self.report.warnings.push(warning.at_line(last.line_number));
+        }
+    }
+}
+
+pub fn check(program: &ast::Program) -> Report {
+    let layout = match Layout::new(program) {
+        Ok(layout) => layout,
+        Err(error) => {
+            return Report {
+                errors: vec![error],
+                warnings: Vec::new(),
+            }
+        }
+    };
+
+    let mut checker = Checker {
+        layout,
+        report: Report::default(),
+    };
+    checker.check_targets();
+    checker.check_reachability();
+    checker.check_assignments();
+    checker.check_end();
+    checker.report
+}
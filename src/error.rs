@@ -0,0 +1,284 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    InvalidLineNumber,
+    InvalidNumber,
+    InvalidToken,
+    UnterminatedString,
+
+    InvalidSyntax,
+    UnexpectedToken,
+    ExpectedExpression,
+    MismatchedParenthesis,
+    UnmatchedBlock,
+    DuplicateDefinition,
+    DuplicateLineNumber,
+
+    TypeMismatch,
+
+    DivisionByZero,
+    UndefinedVariable,
+    UndefinedFunction,
+    IllegalFunctionCall,
+    SubscriptOutOfRange,
+    InvalidTarget,
+    NestingTooDeep,
+    UnmatchedFlow,
+    ArgumentCount,
+    IllegalStatement,
+    NoDisplay,
+
+    Io,
+
+    UnreachableLine,
+    ReadBeforeAssignment,
+    MissingEnd,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ErrorCode::InvalidLineNumber => "E0101",
+            ErrorCode::InvalidNumber => "E0102",
+            ErrorCode::InvalidToken => "E0103",
+            ErrorCode::UnterminatedString => "E0104",
+
+            ErrorCode::InvalidSyntax => "E0201",
+            ErrorCode::UnexpectedToken => "E0202",
+            ErrorCode::ExpectedExpression => "E0203",
+            ErrorCode::MismatchedParenthesis => "E0204",
+            ErrorCode::UnmatchedBlock => "E0205",
+            ErrorCode::DuplicateDefinition => "E0206",
+            ErrorCode::DuplicateLineNumber => "E0207",
+
+            ErrorCode::TypeMismatch => "E0301",
+
+            ErrorCode::DivisionByZero => "E0401",
+            ErrorCode::UndefinedVariable => "E0402",
+            ErrorCode::UndefinedFunction => "E0403",
+            ErrorCode::IllegalFunctionCall => "E0404",
+            ErrorCode::SubscriptOutOfRange => "E0405",
+            ErrorCode::InvalidTarget => "E0406",
+            ErrorCode::NestingTooDeep => "E0407",
+            ErrorCode::UnmatchedFlow => "E0408",
+            ErrorCode::ArgumentCount => "E0409",
+            ErrorCode::IllegalStatement => "E0410",
+            ErrorCode::NoDisplay => "E0411",
+
+            ErrorCode::Io => "E0501",
+
+            ErrorCode::UnreachableLine => "W0101",
+            ErrorCode::ReadBeforeAssignment => "W0102",
+            ErrorCode::MissingEnd => "W0103",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Span {
+    pub fn at(column: u32) -> Span {
+        Span {
+            start: column,
+            end: column + 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: ErrorCode,
+    pub message: String,
+    pub line_number: Option<u32>,
+    pub file_line: Option<u32>,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SbasicError {
+    Lex(Diagnostic),
+    Parse(Diagnostic),
+    Type(Diagnostic),
+    Runtime(Diagnostic),
+    Io(Diagnostic),
+    Warning(Diagnostic),
+}
+
+fn diagnostic(code: ErrorCode, message: String) -> Diagnostic {
+    Diagnostic {
+        code,
+        message,
+        line_number: None,
+        file_line: None,
+        span: None,
+    }
+}
+
+impl SbasicError {
+    pub fn lex(code: ErrorCode, message: impl Into<String>) -> SbasicError {
+        SbasicError::Lex(diagnostic(code, message.into()))
+    }
+
+    pub fn parse(code: ErrorCode, message: impl Into<String>) -> SbasicError {
+        SbasicError::Parse(diagnostic(code, message.into()))
+    }
+
+    pub fn type_mismatch(message: impl Into<String>) -> SbasicError {
+        SbasicError::Type(diagnostic(ErrorCode::TypeMismatch, message.into()))
+    }
+
+    pub fn runtime(code: ErrorCode, message: impl Into<String>) -> SbasicError {
+        SbasicError::Runtime(diagnostic(code, message.into()))
+    }
+
+    pub fn io(message: impl Into<String>) -> SbasicError {
+        SbasicError::Io(diagnostic(ErrorCode::Io, message.into()))
+    }
+
+    pub fn warning(code: ErrorCode, message: impl Into<String>) -> SbasicError {
+        SbasicError::Warning(diagnostic(code, message.into()))
+    }
+
+    pub fn diagnostic(&self) -> &Diagnostic {
+        match self {
+            SbasicError::Lex(diagnostic)
+            | SbasicError::Parse(diagnostic)
+            | SbasicError::Type(diagnostic)
+            | SbasicError::Runtime(diagnostic)
+            | SbasicError::Io(diagnostic)
+            | SbasicError::Warning(diagnostic) => diagnostic,
+        }
+    }
+
+    fn diagnostic_mut(&mut self) -> &mut Diagnostic {
+        match self {
+            SbasicError::Lex(diagnostic)
+            | SbasicError::Parse(diagnostic)
+            | SbasicError::Type(diagnostic)
+            | SbasicError::Runtime(diagnostic)
+            | SbasicError::Io(diagnostic)
+            | SbasicError::Warning(diagnostic) => diagnostic,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.diagnostic().code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.diagnostic().message
+    }
+
+    pub fn line_number(&self) -> Option<u32> {
+        self.diagnostic().line_number
+    }
+
+    pub fn file_line(&self) -> Option<u32> {
+        self.diagnostic().file_line
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.diagnostic().span
+    }
+
+    fn kind_name(&self) -> &'static str {
+        match self {
+            SbasicError::Lex(_) => "syntax error",
+            SbasicError::Parse(_) => "parse error",
+            SbasicError::Type(_) => "type error",
+            SbasicError::Runtime(_) => "runtime error",
+            SbasicError::Io(_) => "I/O error",
+            SbasicError::Warning(_) => "warning",
+        }
+    }
+
+    // Errors raised deep inside an expression keep the location they were first given.
+    pub fn at(mut self, line_number: u32, span: Span) -> SbasicError {
+        let diagnostic = self.diagnostic_mut();
+        if diagnostic.line_number.is_none() {
+            diagnostic.line_number = Some(line_number);
+        }
+        if diagnostic.span.is_none() {
+            diagnostic.span = Some(span);
+        }
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> SbasicError {
+        let diagnostic = self.diagnostic_mut();
+        if diagnostic.span.is_none() {
+            diagnostic.span = Some(span);
+        }
+        self
+    }
+
+    pub fn at_line(mut self, line_number: u32) -> SbasicError {
+        let diagnostic = self.diagnostic_mut();
+        if diagnostic.line_number.is_none() {
+            diagnostic.line_number = Some(line_number);
+        }
+        self
+    }
+
+    pub fn at_file_line(mut self, file_line: u32) -> SbasicError {
+        self.diagnostic_mut().file_line = Some(file_line);
+        self
+    }
+
+    pub fn render(&self, source_line: Option<&str>) -> String {
+        let mut rendered = format!("{} {}", self.kind_name(), self.code().as_str());
+        match (self.line_number(), self.file_line()) {
+            (Some(line_number), Some(file_line)) => {
+                rendered += &format!(" at line {} (file line {})", line_number, file_line)
+            }
+            (Some(line_number), None) => rendered += &format!(" at line {}", line_number),
+            (None, Some(file_line)) => rendered += &format!(" at file line {}", file_line),
+            (None, None) => {}
+        }
+        if let Some(span) = self.span() {
+            rendered += &format!(", column {}", span.start);
+        }
+        rendered += &format!(": {}", self.message());
+
+        if let (Some(source_line), Some(span)) = (source_line, self.span()) {
+            let width = span.end.saturating_sub(span.start).max(1) as usize;
+            rendered += &format!(
+                "\n    {}\n    {}{}",
+                source_line,
+                " ".repeat(span.start as usize),
+                "^".repeat(width)
+            );
+        }
+
+        rendered
+    }
+}
+
+impl fmt::Display for SbasicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.line_number(), self.span()) {
+            (Some(line_number), Some(span)) => write!(
+                f,
+                "ERR [{:?} | {}]: {}",
+                line_number,
+                span.start,
+                self.message()
+            ),
+            (Some(line_number), None) => write!(f, "ERR [{:?}]: {}", line_number, self.message()),
+            _ => write!(f, "ERR: {}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for SbasicError {}
+
+impl From<std::io::Error> for SbasicError {
+    fn from(error: std::io::Error) -> SbasicError {
+        SbasicError::io(error.to_string())
+    }
+}
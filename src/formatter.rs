@@ -0,0 +1,38 @@
+use crate::builtins;
+use crate::lexer;
+use crate::token;
+
+// Operators written as words, like AND and MOD, are normalized along with the keywords.
+fn is_normalized(token: &token::Token) -> bool {
+    match token {
+        token::Token::Variable(name) => builtins::is_builtin(name),
+        token::Token::Comment(_) | token::Token::Text(_) | token::Token::Number(_) => false,
+        _ => true,
+    }
+}
+
+// Only keywords and builtin function names change, everything else is printed as it was typed.
+pub fn format_line(line: &str) -> String {
+    let instruction = match lexer::tokenize_line(line, lexer::IdentifierCase::Sensitive) {
+        Ok(instruction) => instruction,
+        Err(_) => return line.to_string(),
+    };
+
+    let mut characters: Vec<char> = line.chars().collect();
+    for (position, token) in instruction.tokens.iter() {
+        if !is_normalized(token) {
+            continue;
+        }
+
+        let start = *position as usize;
+        let end = characters[start..]
+            .iter()
+            .position(|character| !(character.is_ascii_alphanumeric() || *character == '$'))
+            .map_or(characters.len(), |length| start + length);
+        for character in characters[start..end].iter_mut() {
+            character.make_ascii_uppercase();
+        }
+    }
+
+    characters.into_iter().collect()
+}
@@ -0,0 +1,181 @@
+use crate::error::SbasicError;
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes.iter() {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes.iter() {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn push_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Stored (uncompressed) deflate blocks keep the encoder small, the files are larger but exact.
+fn encode_png(width: u32, height: u32, pixels: &[[u8; 3]]) -> Vec<u8> {
+    let mut raw = Vec::with_capacity((height * (width * 3 + 1)) as usize);
+    for row in pixels.chunks(width.max(1) as usize) {
+        raw.push(0);
+        raw.extend(row.iter().flatten());
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = raw.chunks(u16::MAX as usize).collect();
+    for (index, block) in blocks.iter().enumerate() {
+        zlib.push((index + 1 == blocks.len()) as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    if blocks.is_empty() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+    push_chunk(&mut png, b"IHDR", &header);
+    push_chunk(&mut png, b"IDAT", &zlib);
+    push_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn encode_ppm(width: u32, height: u32, pixels: &[[u8; 3]]) -> Vec<u8> {
+    let mut ppm = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    ppm.extend(pixels.iter().flatten());
+    ppm
+}
+
+// The format follows the file extension, .png or .ppm.
+pub fn save(path: &str, width: u32, height: u32, pixels: &[[u8; 3]]) -> Result<(), SbasicError> {
+    let extension = std::path::Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+
+    let bytes = match extension.as_deref() {
+        Some("png") => encode_png(width, height, pixels),
+        Some("ppm") => encode_ppm(width, height, pixels),
+        _ => {
+            return Err(SbasicError::io(format!(
+                "Cannot save {}, images must end in .png or .ppm.",
+                path
+            )))
+        }
+    };
+
+    std::fs::write(path, bytes)
+        .map_err(|e| SbasicError::io(format!("Cannot save {}: {}.", path, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn be32(bytes: &[u8]) -> u32 {
+        u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    // Splits a PNG into its chunks, checking every CRC on the way.
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(
+            png[..8],
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n']
+        );
+        let mut chunks = Vec::new();
+        let mut position = 8;
+        while position < png.len() {
+            let length = be32(&png[position..]) as usize;
+            let body = &png[position + 4..position + 8 + length];
+            assert_eq!(be32(&png[position + 8 + length..]), crc32(body));
+            chunks.push(([body[0], body[1], body[2], body[3]], body[4..].to_vec()));
+            position += 12 + length;
+        }
+        chunks
+    }
+
+    // Reads back the stored deflate blocks written by encode_png.
+    fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+        assert_eq!(zlib[..2], [0x78, 0x01]);
+        let mut raw = Vec::new();
+        let mut position = 2;
+        loop {
+            let last = zlib[position] == 1;
+            let length = u16::from_le_bytes([zlib[position + 1], zlib[position + 2]]);
+            let complement = u16::from_le_bytes([zlib[position + 3], zlib[position + 4]]);
+            assert_eq!(length, !complement);
+            raw.extend_from_slice(&zlib[position + 5..position + 5 + length as usize]);
+            position += 5 + length as usize;
+            if last {
+                break;
+            }
+        }
+        assert_eq!(be32(&zlib[position..]), adler32(&raw));
+        assert_eq!(position + 4, zlib.len());
+        raw
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(b""), 1);
+    }
+
+    #[test]
+    fn ppm_is_a_header_and_raw_pixels() {
+        let ppm = encode_ppm(2, 1, &[[1, 2, 3], [255, 0, 128]]);
+        assert_eq!(ppm, b"P6\n2 1\n255\n\x01\x02\x03\xff\x00\x80");
+    }
+
+    #[test]
+    fn png_round_trips_through_its_chunks() {
+        for (width, height) in [(2, 2), (300, 120), (0, 0)] {
+            let pixels: Vec<[u8; 3]> = (0..width * height)
+                .map(|index| [index as u8, (index / 7) as u8, 200])
+                .collect();
+            let png = encode_png(width, height, &pixels);
+            let chunks = chunks(&png);
+
+            let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+            assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+            assert_eq!(be32(&chunks[0].1), width);
+            assert_eq!(be32(&chunks[0].1[4..]), height);
+            assert_eq!(chunks[0].1[8..], [8, 2, 0, 0, 0]);
+
+            let raw = inflate_stored(&chunks[1].1);
+            let mut expected = Vec::new();
+            for row in pixels.chunks(width.max(1) as usize) {
+                expected.push(0);
+                expected.extend(row.iter().flatten());
+            }
+            assert_eq!(raw, expected);
+        }
+    }
+}
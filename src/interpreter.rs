@@ -1,317 +1,1463 @@
+use crate::ast;
+use crate::builtins;
+use crate::error::{ErrorCode, SbasicError, Span};
+use crate::image;
 use crate::lexer;
-use crate::parser;
 use crate::renderer;
 use crate::token;
 use crate::value_type;
 
+use std::cmp::Ordering;
 use std::collections::BTreeMap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 
-pub fn interpret(instructions: Vec<lexer::Instruction>) -> Result<String, String> {
-    let mut variables: HashMap<String, value_type::ValueType> = HashMap::new();
-    let mut instruction_ids = BTreeMap::new();
-    let mut instruction_map = BTreeMap::new();
+pub const DEFAULT_MAX_GOSUB_DEPTH: usize = 256;
+// Programs run on a thread with STACK_SIZE bytes of stack, a debug build needs about 70 KiB per call.
+pub const STACK_SIZE: usize = 256 << 20;
+pub const MAX_CALL_DEPTH: usize = STACK_SIZE / (256 << 10);
+pub const DEFAULT_FPS: u32 = 60;
+pub const MAX_SCREEN_SIZE: i32 = 16384;
+const EVENT_INTERVAL: Duration = Duration::from_millis(10);
 
-    for (index, instruction) in instructions.iter().enumerate() {
-        instruction_map.insert(&instruction.line_number, index);
-        instruction_ids.insert(&instruction.line_number, &instruction.tokens);
+// SUB, FUNCTION and DEF FN calls recurse on the Rust stack, max_call_depth is capped at MAX_CALL_DEPTH.
+pub struct Config {
+    pub max_gosub_depth: usize,
+    pub max_call_depth: usize,
+    pub identifier_case: lexer::IdentifierCase,
+    pub headless: bool,
+    pub screenshot: Option<String>,
+    pub frames: Option<usize>,
+    pub fps: Option<u32>,
+    pub vsync: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_gosub_depth: DEFAULT_MAX_GOSUB_DEPTH,
+            max_call_depth: MAX_CALL_DEPTH,
+            identifier_case: lexer::IdentifierCase::default(),
+            headless: false,
+            screenshot: None,
+            frames: None,
+            fps: None,
+            vsync: false,
+        }
     }
+}
+
+#[derive(Default)]
+pub struct Globals {
+    variables: HashMap<String, value_type::ValueType>,
+    option_base: usize,
+}
+
+struct LoopFrame {
+    variable: String,
+    limit: value_type::ValueType,
+    step: value_type::ValueType,
+    instruction_index: usize,
+}
+
+struct UserFunction<'a> {
+    parameters: &'a [String],
+    body: &'a ast::Expr,
+}
+
+pub(crate) struct Procedure<'a> {
+    pub(crate) parameters: &'a [String],
+    pub(crate) is_function: bool,
+    pub(crate) start_index: usize,
+    pub(crate) end_index: usize,
+}
 
-    let instruction_numbers: Vec<_> = instruction_map.keys().clone().collect();
-    let instruction_amount = instruction_numbers.len();
+pub(crate) struct IfClause {
+    pub(crate) next_clause: usize,
+    pub(crate) end_if: usize,
+}
 
-    let mut instruction_index = 0;
-    let mut instruction_goto = false;
+struct Scope {
+    variables: HashMap<String, value_type::ValueType>,
+    shared: HashSet<String>,
+}
 
-    let mut program: renderer::Renderer = renderer::Renderer::default();
+enum Flow {
+    Next,
+    Jump(usize),
+    ExitProcedure,
+}
 
-    let mut graphics = false;
-    let mut running = true;
+fn loop_continues(
+    value: &value_type::ValueType,
+    limit: &value_type::ValueType,
+    step: &value_type::ValueType,
+) -> bool {
+    if *step < value_type::ValueType::Number(0.0) {
+        value >= limit
+    } else {
+        value <= limit
+    }
+}
 
-    while running {
-        if graphics {
-            program.update(&mut running);
+// Every EXIT DO and EXIT FOR of a line, including those in both branches of an inline IF.
+fn loop_exits<'a>(statement: &'a ast::Stmt, exits: &mut Vec<&'a ast::Stmt>) {
+    match statement {
+        ast::Stmt::ExitDo | ast::Stmt::ExitFor => exits.push(statement),
+        ast::Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            for statement in then_branch.iter().chain(else_branch.iter().flatten()) {
+                loop_exits(statement, exits);
+            }
         }
+        _ => {}
+    }
+}
 
-        let instruction_number = instruction_numbers[instruction_index];
-        let tokens = &instruction_ids[instruction_number];
-        let mut token_iter = tokens.iter().peekable();
+fn apply_operator(
+    operator: &token::Token,
+    operand_1: value_type::ValueType,
+    operand_2: value_type::ValueType,
+) -> Result<value_type::ValueType, SbasicError> {
+    if operator.is_comparison_operator() {
+        let ordering = operand_1.compare(&operand_2)?;
+        let result = match *operator {
+            token::Token::Equals => ordering == Some(Ordering::Equal),
+            token::Token::NotEqual => ordering != Some(Ordering::Equal),
+            token::Token::LessThan => ordering == Some(Ordering::Less),
+            token::Token::GreaterThan => ordering == Some(Ordering::Greater),
+            token::Token::LessThanEqual => ordering.is_some_and(Ordering::is_le),
+            token::Token::GreaterThanEqual => ordering.is_some_and(Ordering::is_ge),
 
-        if !tokens.is_empty() {
-            let (position, ref token) = *token_iter.next().unwrap();
-            instruction_goto = false;
+            _ => unreachable!(),
+        };
 
-            match *token {
-                token::Token::Print => match parser::parse_and_eval(&mut token_iter, &variables) {
-                    Ok(value_type::ValueType::Text(x)) => println!("{}", x),
-                    Ok(value_type::ValueType::Number(x)) => println!("{}", x),
-                    Ok(value_type::ValueType::Bool(x)) => println!("{}", x),
-                    Err(_) => {
-                        return Err(format!(
-                            "ERR [{:?} | {}]: PRINT must be followed by a valid expression.",
-                            instruction_number, position
-                        ))
+        return Ok(value_type::ValueType::Bool(result));
+    }
+
+    match *operator {
+        token::Token::Plus => operand_1 + operand_2,
+        token::Token::Minus => operand_1 - operand_2,
+        token::Token::Multiply => operand_1 * operand_2,
+        token::Token::Divide => operand_1 / operand_2,
+        token::Token::IntegerDivide => operand_1.integer_divide(operand_2),
+        token::Token::Power => operand_1.power(operand_2),
+        token::Token::Mod => operand_1 % operand_2,
+        token::Token::And => operand_1 & operand_2,
+        token::Token::Or => operand_1 | operand_2,
+        token::Token::Xor => operand_1 ^ operand_2,
+
+        _ => unreachable!(),
+    }
+}
+
+fn default_value(name: &str) -> value_type::ValueType {
+    if name.ends_with('$') {
+        value_type::ValueType::Text(String::new())
+    } else {
+        value_type::ValueType::Number(0.0)
+    }
+}
+
+fn block_error(line: &ast::Line, code: ErrorCode, message: String) -> SbasicError {
+    SbasicError::parse(code, message).at(line.line_number, Span::at(line.position))
+}
+
+fn to_graphics_argument(statement: &str, value: value_type::ValueType) -> Result<i32, SbasicError> {
+    match value {
+        value_type::ValueType::Number(number) => Ok(number.round() as i32),
+        value => Err(SbasicError::type_mismatch(format!(
+            "{} expects numeric arguments but found {}.",
+            statement, value
+        ))),
+    }
+}
+
+pub(crate) struct Layout<'a> {
+    pub(crate) lines: &'a [ast::Line],
+    pub(crate) instruction_map: BTreeMap<u32, usize>,
+    pub(crate) procedures: HashMap<&'a str, Procedure<'a>>,
+    pub(crate) if_clauses: HashMap<usize, IfClause>,
+    pub(crate) loop_targets: HashMap<usize, usize>,
+    pub(crate) do_exits: HashMap<usize, usize>,
+    pub(crate) for_exits: HashMap<usize, usize>,
+}
+
+struct Interpreter<'a> {
+    config: &'a Config,
+    lines: &'a [ast::Line],
+    layout: Layout<'a>,
+    user_functions: HashMap<&'a str, UserFunction<'a>>,
+
+    variables: HashMap<String, value_type::ValueType>,
+    scopes: Vec<Scope>,
+    loop_stack: Vec<LoopFrame>,
+    // The GOSUB line and the statements left in its inline branch, which run after RETURN.
+    return_stack: Vec<(usize, &'a [ast::Stmt])>,
+    option_base: usize,
+
+    program: Option<Box<dyn renderer::Renderer>>,
+    graphics: bool,
+    frames: usize,
+    last_present: Instant,
+    last_wait: Instant,
+    last_poll: Instant,
+    screenshot_saved: bool,
+    running: bool,
+}
+
+impl<'a> Layout<'a> {
+    pub(crate) fn new(program: &'a ast::Program) -> Result<Self, SbasicError> {
+        let mut instruction_map = BTreeMap::new();
+        for (index, line) in program.lines.iter().enumerate() {
+            instruction_map.entry(line.line_number).or_insert(index);
+        }
+
+        let mut layout = Layout {
+            lines: &program.lines,
+            instruction_map,
+            procedures: HashMap::new(),
+            if_clauses: HashMap::new(),
+            loop_targets: HashMap::new(),
+            do_exits: HashMap::new(),
+            for_exits: HashMap::new(),
+        };
+
+        layout.find_procedures()?;
+        layout.find_if_blocks()?;
+        layout.find_loops()?;
+        Ok(layout)
+    }
+
+    fn find_procedures(&mut self) -> Result<(), SbasicError> {
+        let mut open_procedure: Option<(&'a str, Procedure<'a>)> = None;
+        let lines: &'a [ast::Line] = self.lines;
+
+        for (index, line) in lines.iter().enumerate() {
+            let (name, parameters, is_function) = match line.statement {
+                ast::Stmt::Sub {
+                    ref name,
+                    ref parameters,
+                } => (name, parameters, false),
+                ast::Stmt::Function {
+                    ref name,
+                    ref parameters,
+                } => (name, parameters, true),
+
+                ast::Stmt::EndSub | ast::Stmt::EndFunction => {
+                    let is_function = line.statement == ast::Stmt::EndFunction;
+                    match open_procedure.take() {
+                        Some((name, mut procedure)) if procedure.is_function == is_function => {
+                            procedure.end_index = index;
+                            self.procedures.insert(name, procedure);
+                        }
+                        _ => {
+                            let kind = if is_function { "FUNCTION" } else { "SUB" };
+                            return Err(block_error(
+                                line,
+                                ErrorCode::UnmatchedBlock,
+                                format!("END {} without a matching {}.", kind, kind),
+                            ));
+                        }
                     }
+                    continue;
+                }
+
+                _ => continue,
+            };
+
+            if open_procedure.is_some() {
+                return Err(block_error(
+                    line,
+                    ErrorCode::UnmatchedBlock,
+                    String::from("SUB and FUNCTION cannot be nested."),
+                ));
+            }
+
+            if self.procedures.contains_key(name.as_str()) {
+                return Err(block_error(
+                    line,
+                    ErrorCode::DuplicateDefinition,
+                    format!("{} is already defined.", name),
+                ));
+            }
+
+            open_procedure = Some((
+                name,
+                Procedure {
+                    parameters,
+                    is_function,
+                    start_index: index,
+                    end_index: index,
                 },
+            ));
+        }
 
-                token::Token::Input => match token_iter.next() {
-                    Some(&(_, token::Token::Variable(ref variable))) => {
-                        let mut input = String::new();
+        match open_procedure {
+            Some((name, procedure)) => Err(block_error(
+                &self.lines[procedure.start_index],
+                ErrorCode::UnmatchedBlock,
+                format!(
+                    "{} is missing its END {}.",
+                    name,
+                    if procedure.is_function {
+                        "FUNCTION"
+                    } else {
+                        "SUB"
+                    }
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn find_if_blocks(&mut self) -> Result<(), SbasicError> {
+        let mut open_blocks: Vec<Vec<usize>> = Vec::new();
+
+        for (index, line) in self.lines.iter().enumerate() {
+            let error = |message: &str| {
+                Err(block_error(
+                    line,
+                    ErrorCode::UnmatchedBlock,
+                    message.to_string(),
+                ))
+            };
+
+            match line.statement {
+                ast::Stmt::IfBlock(_) => open_blocks.push(vec![index]),
+                ast::Stmt::ElseIf(_) | ast::Stmt::Else => {
+                    let clauses = match open_blocks.last_mut() {
+                        Some(clauses) => clauses,
+                        None if line.statement == ast::Stmt::Else => {
+                            return error("ELSE without IF.")
+                        }
+                        None => return error("ELSEIF without IF."),
+                    };
 
-                        std::io::stdin()
-                            .read_line(&mut input)
-                            .expect("failed to read line");
-                        input = input.trim().to_string();
-                        let value = value_type::ValueType::Text(input);
-                        variables.insert(variable.clone().to_string(), value);
+                    if self.lines[*clauses.last().unwrap()].statement == ast::Stmt::Else {
+                        return error("ELSE must be the last clause of an IF block.");
                     }
+                    clauses.push(index);
+                }
+                ast::Stmt::EndIf => {
+                    let mut clauses = match open_blocks.pop() {
+                        Some(clauses) => clauses,
+                        None => return error("END IF without IF."),
+                    };
 
-                    _ => {
-                        return Err(format!(
-                            "ERR [{:?} | {}]: INPUT must be followed by an identifier",
-                            instruction_number,
-                            position + 5
-                        ))
+                    clauses.push(index);
+                    for pair in clauses.windows(2) {
+                        self.if_clauses.insert(
+                            pair[0],
+                            IfClause {
+                                next_clause: pair[1],
+                                end_if: index,
+                            },
+                        );
                     }
-                },
+                }
+                _ => {}
+            }
+        }
 
-                token::Token::Let => {
-                    match (
-                        token_iter.next(),
-                        token_iter.next(),
-                        parser::parse_and_eval(&mut token_iter, &variables),
-                    ) {
-                        (
-                            Some(&(_, token::Token::Variable(ref variable))),
-                            Some(&(_, token::Token::Equals)),
-                            Ok(ref value),
-                        ) => {
-                            variables.insert(variable.clone().to_string(), value.clone());
-                        }
+        match open_blocks.pop() {
+            Some(clauses) => Err(block_error(
+                &self.lines[clauses[0]],
+                ErrorCode::UnmatchedBlock,
+                String::from("IF without END IF."),
+            )),
+            None => Ok(()),
+        }
+    }
 
-                        (_, _, Err(e)) => {
-                            return Err(format!(
-                                "ERR [{:?} | {}]: LET {}.",
-                                instruction_number, position, e
-                            ))
-                        }
+    fn find_loops(&mut self) -> Result<(), SbasicError> {
+        let mut open_loops: Vec<usize> = Vec::new();
+        let mut open_fors: Vec<usize> = Vec::new();
 
-                        _ => {
-                            return Err(format!(
-                                "ERR [{:?} | {}]: Invalid syntax for LET.",
-                                instruction_number, position
-                            ))
+        for (index, line) in self.lines.iter().enumerate() {
+            let error = |message: &str| {
+                Err(block_error(
+                    line,
+                    ErrorCode::UnmatchedBlock,
+                    message.to_string(),
+                ))
+            };
+
+            match line.statement {
+                ast::Stmt::While(_) | ast::Stmt::Do(_) => open_loops.push(index),
+                ast::Stmt::Wend | ast::Stmt::Loop(_) => {
+                    let is_wend = line.statement == ast::Stmt::Wend;
+                    let start = match open_loops.pop() {
+                        Some(start)
+                            if matches!(self.lines[start].statement, ast::Stmt::While(_))
+                                == is_wend =>
+                        {
+                            start
                         }
+                        _ if is_wend => return error("WEND without WHILE."),
+                        _ => return error("LOOP without DO."),
+                    };
+
+                    self.loop_targets.insert(start, index);
+                    self.loop_targets.insert(index, start);
+                }
+                ast::Stmt::For { .. } => open_fors.push(index),
+                ast::Stmt::Next(_) => {
+                    if let Some(start) = open_fors.pop() {
+                        self.loop_targets.insert(start, index);
                     }
                 }
+                _ => {}
+            }
 
-                token::Token::If => {
-                    match (
-                        parser::parse_and_eval(&mut token_iter, &variables),
-                        token_iter.next(),
-                        token_iter.next(),
-                    ) {
-                        (
-                            Ok(value_type::ValueType::Bool(ref value)),
-                            Some(&(_, token::Token::Then)),
-                            Some(&(_, token::Token::Number(ref number))),
-                        ) => {
-                            if *value {
-                                instruction_goto = true;
-                                let line_number = *number as u32;
-                                match instruction_map.get(&line_number) {
-                                    Some(index) => instruction_index = *index,
-                                    _ => {
-                                        return Err(format!(
-                                            "ERR [{:?} | {}]: Invalid target for IF.",
-                                            instruction_number, position
-                                        ))
-                                    }
-                                }
-                            }
-                        }
+            let mut exits = Vec::new();
+            loop_exits(&line.statement, &mut exits);
+            for exit in exits {
+                if *exit == ast::Stmt::ExitDo {
+                    let start = open_loops
+                        .iter()
+                        .rev()
+                        .find(|&&start| matches!(self.lines[start].statement, ast::Stmt::Do(_)));
+                    match start {
+                        Some(&start) => self.do_exits.insert(index, start),
+                        None => return error("EXIT DO outside of a DO loop."),
+                    };
+                } else {
+                    match open_fors.last() {
+                        Some(&start) => self.for_exits.insert(index, start),
+                        None => return error("EXIT FOR outside of a FOR loop."),
+                    };
+                }
+            }
+        }
+
+        match open_loops.pop() {
+            Some(start) => {
+                let message = if matches!(self.lines[start].statement, ast::Stmt::While(_)) {
+                    "WHILE without WEND."
+                } else {
+                    "DO without LOOP."
+                };
+                Err(block_error(
+                    &self.lines[start],
+                    ErrorCode::UnmatchedBlock,
+                    String::from(message),
+                ))
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn loop_end(&self, start: usize) -> Result<usize, SbasicError> {
+        match self.loop_targets.get(&start) {
+            Some(&end) => Ok(end),
+            None => Err(SbasicError::runtime(
+                ErrorCode::UnmatchedFlow,
+                "FOR without NEXT.",
+            )),
+        }
+    }
+
+    pub(crate) fn find_line(
+        &self,
+        line_number: u32,
+        statement: &str,
+    ) -> Result<usize, SbasicError> {
+        match self.instruction_map.get(&line_number) {
+            Some(index) => Ok(*index),
+            None => Err(SbasicError::runtime(
+                ErrorCode::InvalidTarget,
+                format!("Invalid target {} for {}.", line_number, statement),
+            )),
+        }
+    }
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(program: &'a ast::Program, config: &'a Config) -> Result<Self, SbasicError> {
+        Ok(Interpreter {
+            config,
+            lines: &program.lines,
+            layout: Layout::new(program)?,
+            user_functions: HashMap::new(),
+
+            variables: HashMap::new(),
+            scopes: Vec::new(),
+            loop_stack: Vec::new(),
+            return_stack: Vec::new(),
+            option_base: 0,
+
+            program: None,
+            graphics: false,
+            frames: 0,
+            last_present: Instant::now(),
+            last_wait: Instant::now(),
+            last_poll: Instant::now(),
+            screenshot_saved: false,
+            running: true,
+        })
+    }
+
+    fn variable(&self, name: &str) -> Option<&value_type::ValueType> {
+        match self.scopes.last() {
+            Some(scope) if !scope.shared.contains(name) => scope
+                .variables
+                .get(name)
+                .or_else(|| self.variables.get(name)),
+            _ => self.variables.get(name),
+        }
+    }
+
+    fn variable_mut(&mut self, name: &str) -> Option<&mut value_type::ValueType> {
+        match self.scopes.last_mut() {
+            Some(scope) if !scope.shared.contains(name) && scope.variables.contains_key(name) => {
+                scope.variables.get_mut(name)
+            }
+            _ => self.variables.get_mut(name),
+        }
+    }
+
+    fn assign(&mut self, name: String, value: value_type::ValueType) {
+        match self.scopes.last_mut() {
+            Some(scope) if !scope.shared.contains(&name) => {
+                scope.variables.insert(name, value);
+            }
+            _ => {
+                self.variables.insert(name, value);
+            }
+        }
+    }
+
+    fn push_scope(
+        &mut self,
+        name: &str,
+        parameters: &[String],
+        arguments: Vec<value_type::ValueType>,
+    ) -> Result<(), SbasicError> {
+        if arguments.len() != parameters.len() {
+            return Err(SbasicError::runtime(
+                ErrorCode::ArgumentCount,
+                format!(
+                    "{} expects {} argument(s) but was given {}.",
+                    name,
+                    parameters.len(),
+                    arguments.len()
+                ),
+            ));
+        }
+
+        let max_call_depth = self.config.max_call_depth.min(MAX_CALL_DEPTH);
+        if self.scopes.len() >= max_call_depth {
+            return Err(SbasicError::runtime(
+                ErrorCode::NestingTooDeep,
+                format!("{} nested deeper than {} levels.", name, max_call_depth),
+            ));
+        }
+
+        self.scopes.push(Scope {
+            variables: parameters.iter().cloned().zip(arguments).collect(),
+            shared: HashSet::new(),
+        });
+        Ok(())
+    }
+
+    fn call_user_function(
+        &mut self,
+        name: &str,
+        arguments: Vec<value_type::ValueType>,
+    ) -> Result<value_type::ValueType, SbasicError> {
+        let (parameters, body) = {
+            let function = &self.user_functions[name];
+            (function.parameters, function.body)
+        };
+
+        self.push_scope(name, parameters, arguments)?;
+        let result = self.evaluate(body);
+        self.scopes.pop();
+
+        result
+    }
+
+    fn call_procedure(
+        &mut self,
+        name: &str,
+        arguments: Vec<value_type::ValueType>,
+    ) -> Result<Option<value_type::ValueType>, SbasicError> {
+        let (parameters, is_function, start_index) = match self.layout.procedures.get(name) {
+            Some(procedure) => (
+                procedure.parameters,
+                procedure.is_function,
+                procedure.start_index,
+            ),
+            None => {
+                return Err(SbasicError::runtime(
+                    ErrorCode::UndefinedFunction,
+                    format!("Undefined SUB {}.", name),
+                ))
+            }
+        };
+
+        let return_depth = self.return_stack.len();
+
+        // A procedure's FOR loops never see the caller's, even when they use the same variable.
+        self.push_scope(name, parameters, arguments)?;
+        let loop_stack = std::mem::take(&mut self.loop_stack);
+        let result = self.run(start_index + 1);
+        self.loop_stack = loop_stack;
+        let scope = self.scopes.pop().unwrap();
+
+        self.return_stack.truncate(return_depth);
+        result?;
+
+        if is_function {
+            Ok(Some(match scope.variables.get(name) {
+                Some(value) => value.clone(),
+                None => default_value(name),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn call(
+        &mut self,
+        name: &str,
+        arguments: Vec<value_type::ValueType>,
+    ) -> Result<value_type::ValueType, SbasicError> {
+        if let Some(value_type::ValueType::Array(array)) = self.variable(name) {
+            return array.get(&arguments).cloned();
+        }
+
+        if self.user_functions.contains_key(name) {
+            return self.call_user_function(name, arguments);
+        }
+
+        match self.layout.procedures.get(name) {
+            Some(procedure) if procedure.is_function => {
+                Ok(self.call_procedure(name, arguments)?.unwrap())
+            }
+            Some(_) => Err(SbasicError::type_mismatch(format!(
+                "SUB {} cannot be used in an expression.",
+                name
+            ))),
+            None => builtins::call(name, arguments),
+        }
+    }
+
+    fn evaluate(&mut self, expression: &ast::Expr) -> Result<value_type::ValueType, SbasicError> {
+        match *expression {
+            ast::Expr::Number(number) => Ok(value_type::ValueType::Number(number)),
+            ast::Expr::Text(ref text) => Ok(value_type::ValueType::Text(text.clone())),
+            ast::Expr::Variable(ref name) => match self.variable(name) {
+                Some(value) => Ok(value.clone()),
+                None => Err(SbasicError::runtime(
+                    ErrorCode::UndefinedVariable,
+                    format!("Invalid variable reference {} in expression.", name),
+                )),
+            },
+            ast::Expr::Call(ref name, ref arguments, span) => {
+                let mut values: Vec<value_type::ValueType> = Vec::new();
+                for argument in arguments {
+                    values.push(self.evaluate(argument)?);
+                }
+                self.call(name, values).map_err(|e| e.with_span(span))
+            }
+            ast::Expr::Unary(ref operator, ref operand) => {
+                let value = self.evaluate(operand)?;
+                match *operator {
+                    token::Token::UnaryMinus => -value,
+                    token::Token::Bang | token::Token::Not => !value,
+
+                    _ => unreachable!(),
+                }
+            }
+            ast::Expr::Binary(ref operator, ref left, ref right, span) => {
+                let operand_1 = self.evaluate(left)?;
+
+                // Boolean AND and OR skip their right side once the result is known.
+                match (operator, &operand_1) {
+                    (token::Token::And, value_type::ValueType::Bool(false))
+                    | (token::Token::Or, value_type::ValueType::Bool(true)) => {
+                        return Ok(operand_1);
+                    }
+                    _ => {}
+                }
+
+                let operand_2 = self.evaluate(right)?;
+                apply_operator(operator, operand_1, operand_2).map_err(|e| e.with_span(span))
+            }
+        }
+    }
+
+    fn evaluate_condition(
+        &mut self,
+        statement: &str,
+        condition: &ast::Expr,
+    ) -> Result<bool, SbasicError> {
+        match self.evaluate(condition)? {
+            value_type::ValueType::Bool(condition) => Ok(condition),
+            _ => Err(SbasicError::type_mismatch(format!(
+                "{} condition must be a comparison.",
+                statement
+            ))),
+        }
+    }
+
+    fn loop_condition(&mut self, condition: &ast::LoopCondition) -> Result<bool, SbasicError> {
+        match condition {
+            ast::LoopCondition::While(condition) => self.evaluate_condition("WHILE", condition),
+            ast::LoopCondition::Until(condition) => {
+                Ok(!self.evaluate_condition("UNTIL", condition)?)
+            }
+        }
+    }
+
+    fn skip_to_taken_clause(&mut self, instruction_index: usize) -> Result<Flow, SbasicError> {
+        let mut clause = self.layout.if_clauses[&instruction_index].next_clause;
+
+        while let ast::Stmt::ElseIf(ref condition) = self.lines[clause].statement {
+            if self.evaluate_condition("ELSEIF", condition)? {
+                break;
+            }
+            clause = self.layout.if_clauses[&clause].next_clause;
+        }
+
+        Ok(Flow::Jump(clause + 1))
+    }
+
+    fn evaluate_graphics_argument(
+        &mut self,
+        statement: &str,
+        expression: &ast::Expr,
+    ) -> Result<i32, SbasicError> {
+        let value = self.evaluate(expression)?;
+        to_graphics_argument(statement, value)
+    }
+
+    fn evaluate_angle(
+        &mut self,
+        statement: &str,
+        expression: &ast::Expr,
+    ) -> Result<f32, SbasicError> {
+        match self.evaluate(expression)? {
+            value_type::ValueType::Number(number) => Ok(number as f32),
+            value => Err(SbasicError::type_mismatch(format!(
+                "{} expects numeric arguments but found {}.",
+                statement, value
+            ))),
+        }
+    }
+
+    fn renderer(&mut self) -> Result<&mut dyn renderer::Renderer, SbasicError> {
+        let program = match self.program.take() {
+            Some(program) => program,
+            None => renderer::create(self.config.headless, self.config.vsync)?,
+        };
+        Ok(self.program.insert(program).as_mut())
+    }
+
+    fn save_image(&mut self, path: &str) -> Result<(), SbasicError> {
+        let (width, height, pixels) = self.renderer()?.read_pixels();
+        image::save(path, width, height, &pixels)
+    }
+
+    fn save_screenshot(&mut self) -> Result<(), SbasicError> {
+        let config = self.config;
+        if let (false, Some(path)) = (self.screenshot_saved, &config.screenshot) {
+            if self.program.is_none() {
+                return Err(SbasicError::io(
+                    "Nothing was drawn, no screenshot was saved.",
+                ));
+            }
+            self.save_image(path)?;
+        }
+        self.screenshot_saved = true;
+        Ok(())
+    }
+
+    fn frame_interval(&self) -> Duration {
+        Duration::from_secs(1) / self.config.fps.unwrap_or(DEFAULT_FPS).max(1)
+    }
+
+    fn poll_events(&mut self) {
+        if let (true, Some(program)) = (self.graphics, self.program.as_mut()) {
+            program.poll_events(&mut self.running);
+        }
+        self.last_poll = Instant::now();
+    }
+
+    // Nothing reaches the window until FLIP, or the next tick when --fps presents automatically.
+    fn present(&mut self) -> Result<(), SbasicError> {
+        if self.program.is_none() {
+            return Ok(());
+        }
+
+        // SDL's back buffer is undefined once presented, so the last frame is saved first.
+        self.frames += 1;
+        let last_frame = self.config.frames == Some(self.frames);
+        if last_frame {
+            self.save_screenshot()?;
+        }
+
+        if let Some(program) = self.program.as_mut() {
+            program.present();
+        }
+        self.last_present = Instant::now();
+        self.poll_events();
+        if last_frame {
+            self.running = false;
+        }
+        Ok(())
+    }
+
+    // Events are still handled while waiting so the window can be closed.
+    fn wait_until(&mut self, deadline: Instant) {
+        loop {
+            self.poll_events();
+            let now = Instant::now();
+            if !self.running || now >= deadline {
+                return;
+            }
+            std::thread::sleep((deadline - now).min(EVENT_INTERVAL));
+        }
+    }
+
+    fn run(&mut self, start_index: usize) -> Result<(), SbasicError> {
+        let mut instruction_index = start_index;
+
+        while self.running && instruction_index < self.lines.len() {
+            // Events are handled every EVENT_INTERVAL and on FLIP, WAIT FRAME and SLEEP, not per statement.
+            if self.graphics {
+                let now = Instant::now();
+                if now - self.last_poll >= EVENT_INTERVAL {
+                    self.poll_events();
+                }
+                if self.config.fps.is_some() && now - self.last_present >= self.frame_interval() {
+                    self.present()?;
+                }
+            }
+            if !self.running {
+                break;
+            }
+
+            let line: &'a ast::Line = &self.lines[instruction_index];
+            let flow = match self.execute(&line.statement, instruction_index) {
+                Ok(flow) => flow,
+                Err(e) => return Err(e.at(line.line_number, Span::at(line.position))),
+            };
+
+            match flow {
+                Flow::Next => instruction_index += 1,
+                Flow::Jump(index) => instruction_index = index,
+                Flow::ExitProcedure => return Ok(()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn execute_branch(
+        &mut self,
+        statements: &'a [ast::Stmt],
+        instruction_index: usize,
+    ) -> Result<Flow, SbasicError> {
+        for (position, statement) in statements.iter().enumerate() {
+            let flow = self.execute(statement, instruction_index)?;
+            if matches!(statement, ast::Stmt::Gosub(_)) {
+                if let Some((_, rest)) = self.return_stack.last_mut() {
+                    *rest = &statements[position + 1..];
+                }
+            }
+            if !matches!(flow, Flow::Next) || !self.running {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn execute(
+        &mut self,
+        statement: &'a ast::Stmt,
+        instruction_index: usize,
+    ) -> Result<Flow, SbasicError> {
+        match *statement {
+            ast::Stmt::Print(ref expression) => {
+                let value = self.evaluate(expression)?;
+                println!("{}", value);
+            }
+
+            ast::Stmt::Input(ref variable) => {
+                let mut input = String::new();
+
+                std::io::stdin()
+                    .read_line(&mut input)
+                    .map_err(|e| SbasicError::io(format!("Cannot read input: {}.", e)))?;
+                input = input.trim().to_string();
+                let value = value_type::ValueType::Text(input);
+                self.assign(variable.clone(), value);
+            }
+
+            ast::Stmt::Let {
+                ref name,
+                ref subscripts,
+                ref value,
+            } => match subscripts {
+                None => {
+                    let value = self.evaluate(value)?;
+                    self.assign(name.clone(), value);
+                }
+                Some(subscripts) => {
+                    let mut indices: Vec<value_type::ValueType> = Vec::new();
+                    for subscript in subscripts {
+                        indices.push(self.evaluate(subscript)?);
+                    }
+
+                    let value = self.evaluate(value)?;
+                    match self.variable_mut(name) {
+                        Some(value_type::ValueType::Array(array)) => array.set(&indices, value)?,
                         _ => {
-                            return Err(format!(
-                                "ERR [{:?} | {}]: Invalid syntax for IF.",
-                                instruction_number, position
-                            ))
+                            return Err(SbasicError::type_mismatch(format!(
+                                "{} is not an array.",
+                                name
+                            )))
                         }
                     }
                 }
+            },
 
-                token::Token::Goto => {
-                    instruction_goto = true;
-                    match token_iter.next() {
-                        Some(&(position, token::Token::Number(number))) => {
-                            let line_number = number as u32;
-                            match instruction_map.get(&line_number) {
-                                Some(index) => instruction_index = *index,
-                                _ => {
-                                    return Err(format!(
-                                        "ERR [{:?} | {}]: Invalid target for GOTO",
-                                        instruction_number, position
-                                    ))
-                                }
+            ast::Stmt::Dim(ref arrays) => {
+                for (name, dimensions) in arrays {
+                    if let Some(value_type::ValueType::Array(_)) = self.variable_mut(name) {
+                        return Err(SbasicError::runtime(
+                            ErrorCode::IllegalStatement,
+                            format!("DIM {} is already dimensioned.", name),
+                        ));
+                    }
+
+                    let mut bounds: Vec<usize> = Vec::new();
+                    for dimension in dimensions {
+                        match self.evaluate(dimension)? {
+                            value_type::ValueType::Number(bound) if bound >= 0.0 => {
+                                bounds.push(bound.round() as usize)
+                            }
+                            _ => {
+                                return Err(SbasicError::runtime(
+                                    ErrorCode::IllegalStatement,
+                                    "DIM bounds must be positive numbers.",
+                                ))
                             }
-                        }
-                        Some(&(position, _)) => {
-                            return Err(format!(
-                                "ERR [{:?} | {}]: GOTO must be followed by a valid line number.",
-                                instruction_number, position
-                            ))
-                        }
-                        None => {
-                            return Err(format!(
-                                "ERR [{:?} | {}]: GOTO must be followed by a line number.",
-                                instruction_number,
-                                position + 4
-                            ))
                         }
                     }
+
+                    let array =
+                        value_type::Array::new(self.option_base, bounds, default_value(name))?;
+                    self.assign(name.clone(), value_type::ValueType::Array(array));
                 }
+            }
 
-                token::Token::Screen => {
-                    match (token_iter.next(), token_iter.next(), token_iter.next()) {
-                        (
-                            Some(&(_, token::Token::Number(width))),
-                            Some(&(_, token::Token::Comma)),
-                            Some(&(_, token::Token::Number(height))),
-                        ) => {
-                            program.set_size(width, height);
-                            graphics = true;
-                        }
-                        _ => {
-                            return Err(format!(
-                                "ERR [{:?} | {}]: Invalid syntax for SCREEN.",
-                                instruction_number, position,
-                            ))
-                        }
-                    }
+            ast::Stmt::OptionBase(base) => {
+                if self
+                    .variables
+                    .values()
+                    .any(|value| matches!(value, value_type::ValueType::Array(_)))
+                {
+                    return Err(SbasicError::runtime(
+                        ErrorCode::IllegalStatement,
+                        "OPTION BASE must come before any DIM.",
+                    ));
                 }
 
-                token::Token::Clear => {
-                    program.render_clear();
-                }
-
-                token::Token::Color => {
-                    match (
-                        token_iter.next(),
-                        token_iter.next(),
-                        token_iter.next(),
-                        token_iter.next(),
-                        token_iter.next(),
-                    ) {
-                        (
-                            Some(&(_, token::Token::Number(red))),
-                            Some(&(_, token::Token::Comma)),
-                            Some(&(_, token::Token::Number(green))),
-                            Some(&(_, token::Token::Comma)),
-                            Some(&(_, token::Token::Number(blue))),
-                        ) => {
-                            program.render_setcolor(red, green, blue);
-                        }
-                        _ => {
-                            return Err(format!(
-                                "ERR [{:?} | {}]: Invalid syntax for COLOR.",
-                                instruction_number, position,
-                            ))
-                        }
+                self.option_base = base;
+            }
+
+            ast::Stmt::Rem(_) => {}
+            ast::Stmt::End => self.running = false,
+
+            ast::Stmt::If {
+                ref condition,
+                ref then_branch,
+                ref else_branch,
+            } => {
+                if self.evaluate_condition("IF", condition)? {
+                    return self.execute_branch(then_branch, instruction_index);
+                } else if let Some(else_branch) = else_branch {
+                    return self.execute_branch(else_branch, instruction_index);
+                }
+            }
+
+            ast::Stmt::IfBlock(ref condition) => {
+                if !self.evaluate_condition("IF", condition)? {
+                    return self.skip_to_taken_clause(instruction_index);
+                }
+            }
+
+            ast::Stmt::ElseIf(_) | ast::Stmt::Else => {
+                return Ok(Flow::Jump(
+                    self.layout.if_clauses[&instruction_index].end_if + 1,
+                ))
+            }
+
+            ast::Stmt::EndIf => {}
+
+            ast::Stmt::Goto(target) => {
+                return Ok(Flow::Jump(self.layout.find_line(target, "GOTO")?))
+            }
+
+            ast::Stmt::Gosub(target) => {
+                if self.return_stack.len() >= self.config.max_gosub_depth {
+                    return Err(SbasicError::runtime(
+                        ErrorCode::NestingTooDeep,
+                        format!(
+                            "GOSUB nested deeper than {} levels.",
+                            self.config.max_gosub_depth
+                        ),
+                    ));
+                }
+
+                let index = self.layout.find_line(target, "GOSUB")?;
+                self.return_stack.push((instruction_index, &[]));
+                return Ok(Flow::Jump(index));
+            }
+
+            ast::Stmt::Return => match self.return_stack.pop() {
+                Some((index, rest)) => {
+                    let line = &self.lines[index];
+                    let flow = self
+                        .execute_branch(rest, index)
+                        .map_err(|e| e.at(line.line_number, Span::at(line.position)))?;
+                    return Ok(match flow {
+                        Flow::Next => Flow::Jump(index + 1),
+                        flow => flow,
+                    });
+                }
+                None => {
+                    return Err(SbasicError::runtime(
+                        ErrorCode::UnmatchedFlow,
+                        "RETURN without GOSUB.",
+                    ))
+                }
+            },
+
+            ast::Stmt::For {
+                ref variable,
+                ref start,
+                ref limit,
+                ref step,
+            } => {
+                let start = self.evaluate(start)?;
+                let limit = self.evaluate(limit)?;
+                let step = match step {
+                    Some(step) => self.evaluate(step)?,
+                    None => value_type::ValueType::Number(1.0),
+                };
+
+                if let Some(frame_index) = self
+                    .loop_stack
+                    .iter()
+                    .position(|frame| frame.variable == *variable)
+                {
+                    self.loop_stack.truncate(frame_index);
+                }
+
+                let continues = loop_continues(&start, &limit, &step);
+                self.assign(variable.clone(), start);
+
+                if continues {
+                    self.loop_stack.push(LoopFrame {
+                        variable: variable.clone(),
+                        limit,
+                        step,
+                        instruction_index,
+                    });
+                } else {
+                    return Ok(Flow::Jump(self.layout.loop_end(instruction_index)? + 1));
+                }
+            }
+
+            ast::Stmt::Next(ref variable) => {
+                let frame = match self.loop_stack.pop() {
+                    Some(frame) => frame,
+                    None => {
+                        return Err(SbasicError::runtime(
+                            ErrorCode::UnmatchedFlow,
+                            "NEXT without FOR.",
+                        ))
+                    }
+                };
+
+                if let Some(variable) = variable {
+                    if *variable != frame.variable {
+                        return Err(SbasicError::runtime(
+                            ErrorCode::UnmatchedFlow,
+                            format!("NEXT {} does not match FOR {}.", variable, frame.variable),
+                        ));
                     }
                 }
 
-                token::Token::Dot => {
-                    match (token_iter.next(), token_iter.next(), token_iter.next()) {
-                        (
-                            Some(&(_, token::Token::Number(x))),
-                            Some(&(_, token::Token::Comma)),
-                            Some(&(_, token::Token::Number(y))),
-                        ) => {
-                            program.render_dot(x, y);
-                        }
-                        _ => {
-                            return Err(format!(
-                                "ERR [{:?} | {}]: Invalid syntax for DOT.",
-                                instruction_number, position,
-                            ))
-                        }
+                let value = match self.variable(&frame.variable) {
+                    Some(value) => (value.clone() + frame.step.clone())?,
+                    None => {
+                        return Err(SbasicError::runtime(
+                            ErrorCode::UndefinedVariable,
+                            format!("Invalid loop variable {}.", frame.variable),
+                        ))
                     }
+                };
+
+                let continues = loop_continues(&value, &frame.limit, &frame.step);
+                self.assign(frame.variable.clone(), value);
+
+                if continues {
+                    let for_index = frame.instruction_index;
+                    self.loop_stack.push(frame);
+                    return Ok(Flow::Jump(for_index + 1));
                 }
+            }
 
-                token::Token::Line => {
-                    match (
-                        token_iter.next(),
-                        token_iter.next(),
-                        token_iter.next(),
-                        token_iter.next(),
-                        token_iter.next(),
-                        token_iter.next(),
-                        token_iter.next(),
-                    ) {
-                        (
-                            Some(&(_, token::Token::Number(x1))),
-                            Some(&(_, token::Token::Comma)),
-                            Some(&(_, token::Token::Number(y1))),
-                            Some(&(_, token::Token::To)),
-                            Some(&(_, token::Token::Number(x2))),
-                            Some(&(_, token::Token::Comma)),
-                            Some(&(_, token::Token::Number(y2))),
-                        ) => {
-                            program.render_line(x1, y1, x2, y2);
-                        }
-                        _ => {
-                            return Err(format!(
-                                "ERR [{:?} | {}]: Invalid syntax for LINE.",
-                                instruction_number, position,
-                            ))
-                        }
+            ast::Stmt::While(ref condition) => {
+                if !self.evaluate_condition("WHILE", condition)? {
+                    return Ok(Flow::Jump(self.layout.loop_targets[&instruction_index] + 1));
+                }
+            }
+
+            ast::Stmt::Wend => return Ok(Flow::Jump(self.layout.loop_targets[&instruction_index])),
+
+            ast::Stmt::Do(ref condition) => {
+                if let Some(condition) = condition {
+                    if !self.loop_condition(condition)? {
+                        return Ok(Flow::Jump(self.layout.loop_targets[&instruction_index] + 1));
                     }
                 }
+            }
 
-                token::Token::Circle => {
-                    match (
-                        token_iter.next(),
-                        token_iter.next(),
-                        token_iter.next(),
-                        token_iter.next(),
-                        token_iter.next(),
-                    ) {
-                        (
-                            Some(&(_, token::Token::Number(x))),
-                            Some(&(_, token::Token::Comma)),
-                            Some(&(_, token::Token::Number(y))),
-                            Some(&(_, token::Token::Comma)),
-                            Some(&(_, token::Token::Number(r))),
-                        ) => {
-                            program.render_circle(x, y, r);
-                        }
-                        _ => {
-                            return Err(format!(
-                                "ERR [{:?} | {}]: Invalid syntax for CIRCLE.",
-                                instruction_number, position,
-                            ))
-                        }
+            ast::Stmt::Loop(ref condition) => {
+                let repeat = match condition {
+                    Some(condition) => self.loop_condition(condition)?,
+                    None => true,
+                };
+
+                if repeat {
+                    return Ok(Flow::Jump(self.layout.loop_targets[&instruction_index]));
+                }
+            }
+
+            ast::Stmt::ExitDo => {
+                let start = self.layout.do_exits[&instruction_index];
+                return Ok(Flow::Jump(self.layout.loop_targets[&start] + 1));
+            }
+
+            ast::Stmt::ExitFor => {
+                let start = self.layout.for_exits[&instruction_index];
+                if let Some(frame_index) = self
+                    .loop_stack
+                    .iter()
+                    .rposition(|frame| frame.instruction_index == start)
+                {
+                    self.loop_stack.truncate(frame_index);
+                }
+
+                return Ok(Flow::Jump(self.layout.loop_end(start)? + 1));
+            }
+
+            ast::Stmt::Randomize(ref seed) => match seed {
+                None => builtins::seed_from_time(),
+                Some(seed) => match self.evaluate(seed)? {
+                    value_type::ValueType::Number(seed) => builtins::seed(seed),
+                    _ => {
+                        return Err(SbasicError::type_mismatch(
+                            "RANDOMIZE must be followed by a numeric seed.",
+                        ))
                     }
+                },
+            },
+
+            ast::Stmt::Def {
+                ref name,
+                ref parameters,
+                ref body,
+            } => {
+                self.user_functions
+                    .insert(name, UserFunction { parameters, body });
+            }
+
+            ast::Stmt::Sub { ref name, .. } | ast::Stmt::Function { ref name, .. } => {
+                return Ok(Flow::Jump(
+                    self.layout.procedures[name.as_str()].end_index + 1,
+                ))
+            }
+
+            ast::Stmt::EndSub
+            | ast::Stmt::EndFunction
+            | ast::Stmt::ExitSub
+            | ast::Stmt::ExitFunction => {
+                if self.scopes.is_empty() {
+                    return Err(SbasicError::runtime(
+                        ErrorCode::IllegalStatement,
+                        "END SUB or END FUNCTION outside of a procedure.",
+                    ));
                 }
+                return Ok(Flow::ExitProcedure);
+            }
 
-                token::Token::Rem => {}
-                token::Token::End => running = false,
-                _ => {
-                    return Err(format!(
-                        "ERR [{:?} | {}]: Invalid syntax.",
-                        instruction_number,
-                        position + 4
-                    ))
+            ast::Stmt::Call {
+                ref name,
+                ref arguments,
+            } => {
+                let mut values: Vec<value_type::ValueType> = Vec::new();
+                for argument in arguments {
+                    values.push(self.evaluate(argument)?);
                 }
+                self.call_procedure(name, values)?;
             }
+
+            ast::Stmt::Shared(ref names) => match self.scopes.last_mut() {
+                Some(scope) => {
+                    for name in names {
+                        scope.variables.remove(name);
+                        scope.shared.insert(name.clone());
+                    }
+                }
+                None => {
+                    return Err(SbasicError::runtime(
+                        ErrorCode::IllegalStatement,
+                        "SHARED can only be used inside a SUB or FUNCTION.",
+                    ))
+                }
+            },
+
+            ast::Stmt::Screen { .. }
+            | ast::Stmt::Clear
+            | ast::Stmt::Color { .. }
+            | ast::Stmt::Dot { .. }
+            | ast::Stmt::Line { .. }
+            | ast::Stmt::Circle { .. }
+            | ast::Stmt::Box { .. }
+            | ast::Stmt::Ellipse { .. }
+            | ast::Stmt::Polygon { .. }
+            | ast::Stmt::Paint { .. }
+            | ast::Stmt::Flip
+            | ast::Stmt::Sleep(_)
+            | ast::Stmt::WaitFrame
+            | ast::Stmt::SaveImage(_) => self.draw(statement)?,
         }
 
-        if !instruction_goto {
-            instruction_index += 1;
-            if instruction_index == instruction_amount {
-                break;
+        Ok(Flow::Next)
+    }
+
+    // Graphics and frame timing live apart from execute to keep its stack frame small for deep recursion.
+    fn draw(&mut self, statement: &ast::Stmt) -> Result<(), SbasicError> {
+        match *statement {
+            ast::Stmt::Screen {
+                ref width,
+                ref height,
+            } => {
+                let width = self.evaluate_graphics_argument("SCREEN", width)?;
+                let height = self.evaluate_graphics_argument("SCREEN", height)?;
+                if !(1..=MAX_SCREEN_SIZE).contains(&width)
+                    || !(1..=MAX_SCREEN_SIZE).contains(&height)
+                {
+                    return Err(SbasicError::runtime(
+                        ErrorCode::IllegalFunctionCall,
+                        format!("Invalid SCREEN size {} x {}.", width, height),
+                    ));
+                }
+
+                self.renderer()?.set_size(width, height)?;
+                self.graphics = true;
+            }
+
+            ast::Stmt::Clear => {
+                self.renderer()?.render_clear();
+            }
+
+            ast::Stmt::Color {
+                ref red,
+                ref green,
+                ref blue,
+            } => {
+                let red = self.evaluate_graphics_argument("COLOR", red)?;
+                let green = self.evaluate_graphics_argument("COLOR", green)?;
+                let blue = self.evaluate_graphics_argument("COLOR", blue)?;
+                self.renderer()?.render_setcolor(
+                    red.clamp(0, 255),
+                    green.clamp(0, 255),
+                    blue.clamp(0, 255),
+                );
+            }
+
+            ast::Stmt::Dot { ref x, ref y } => {
+                let x = self.evaluate_graphics_argument("DOT", x)?;
+                let y = self.evaluate_graphics_argument("DOT", y)?;
+                self.renderer()?.render_dot(x, y);
+            }
+
+            ast::Stmt::Line {
+                ref x1,
+                ref y1,
+                ref x2,
+                ref y2,
+            } => {
+                let x1 = self.evaluate_graphics_argument("LINE", x1)?;
+                let y1 = self.evaluate_graphics_argument("LINE", y1)?;
+                let x2 = self.evaluate_graphics_argument("LINE", x2)?;
+                let y2 = self.evaluate_graphics_argument("LINE", y2)?;
+                self.renderer()?.render_line(x1, y1, x2, y2);
+            }
+
+            ast::Stmt::Circle {
+                ref x,
+                ref y,
+                ref radius,
+                ref arc,
+                fill,
+            } => {
+                let x = self.evaluate_graphics_argument("CIRCLE", x)?;
+                let y = self.evaluate_graphics_argument("CIRCLE", y)?;
+                let radius = self.evaluate_graphics_argument("CIRCLE", radius)?;
+                match arc {
+                    Some((start, end)) => {
+                        let start = self.evaluate_angle("CIRCLE", start)?;
+                        let end = self.evaluate_angle("CIRCLE", end)?;
+                        self.renderer()?.render_arc(x, y, radius, start, end, fill);
+                    }
+                    None => self.renderer()?.render_circle(x, y, radius, fill),
+                }
+            }
+
+            ast::Stmt::Box {
+                ref x1,
+                ref y1,
+                ref x2,
+                ref y2,
+                fill,
+            } => {
+                let x1 = self.evaluate_graphics_argument("BOX", x1)?;
+                let y1 = self.evaluate_graphics_argument("BOX", y1)?;
+                let x2 = self.evaluate_graphics_argument("BOX", x2)?;
+                let y2 = self.evaluate_graphics_argument("BOX", y2)?;
+                self.renderer()?.render_box(x1, y1, x2, y2, fill);
+            }
+
+            ast::Stmt::Ellipse {
+                ref x,
+                ref y,
+                ref radius_x,
+                ref radius_y,
+                fill,
+            } => {
+                let x = self.evaluate_graphics_argument("ELLIPSE", x)?;
+                let y = self.evaluate_graphics_argument("ELLIPSE", y)?;
+                let radius_x = self.evaluate_graphics_argument("ELLIPSE", radius_x)?;
+                let radius_y = self.evaluate_graphics_argument("ELLIPSE", radius_y)?;
+                self.renderer()?
+                    .render_ellipse(x, y, radius_x, radius_y, fill);
+            }
+
+            ast::Stmt::Polygon { ref points, fill } => {
+                let mut corners = Vec::with_capacity(points.len());
+                for (x, y) in points.iter() {
+                    let x = self.evaluate_graphics_argument("POLYGON", x)?;
+                    let y = self.evaluate_graphics_argument("POLYGON", y)?;
+                    corners.push((x, y));
+                }
+                self.renderer()?.render_polygon(&corners, fill);
+            }
+
+            ast::Stmt::Paint { ref x, ref y } => {
+                let x = self.evaluate_graphics_argument("PAINT", x)?;
+                let y = self.evaluate_graphics_argument("PAINT", y)?;
+                self.renderer()?.render_paint(x, y);
+            }
+
+            ast::Stmt::Flip => self.present()?,
+
+            ast::Stmt::Sleep(ref milliseconds) => match self.evaluate(milliseconds)? {
+                value @ value_type::ValueType::Number(milliseconds) => {
+                    let deadline = Duration::try_from_secs_f64(milliseconds / 1000.0)
+                        .ok()
+                        .and_then(|duration| Instant::now().checked_add(duration));
+                    match deadline {
+                        Some(deadline) => self.wait_until(deadline),
+                        None => {
+                            return Err(SbasicError::runtime(
+                                ErrorCode::IllegalFunctionCall,
+                                format!("Invalid SLEEP time {}.", value),
+                            ))
+                        }
+                    }
+                }
+                value => {
+                    return Err(SbasicError::type_mismatch(format!(
+                        "SLEEP expects milliseconds but found {}.",
+                        value
+                    )))
+                }
+            },
+
+            ast::Stmt::WaitFrame => {
+                let deadline = self.last_wait + self.frame_interval();
+                self.wait_until(deadline);
+                self.last_wait = Instant::now();
             }
+
+            ast::Stmt::SaveImage(ref path) => match self.evaluate(path)? {
+                value_type::ValueType::Text(path) => self.save_image(&path)?,
+                value => {
+                    return Err(SbasicError::type_mismatch(format!(
+                        "SAVEIMAGE expects a file name but found {}.",
+                        value
+                    )))
+                }
+            },
+
+            _ => unreachable!(),
         }
+
+        Ok(())
     }
+}
+
+pub fn run(
+    program: &ast::Program,
+    start_index: usize,
+    config: &Config,
+    globals: &mut Globals,
+) -> Result<(), SbasicError> {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .name(String::from("sbasic"))
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, || run_program(program, start_index, config, globals))
+            .map_err(|e| SbasicError::io(format!("Cannot start the interpreter: {}.", e)))?
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+fn run_program(
+    program: &ast::Program,
+    start_index: usize,
+    config: &Config,
+    globals: &mut Globals,
+) -> Result<(), SbasicError> {
+    let mut interpreter = Interpreter::new(program, config)?;
+    interpreter.variables = std::mem::take(&mut globals.variables);
+    interpreter.option_base = globals.option_base;
+
+    let result = interpreter
+        .run(start_index)
+        .and_then(|_| interpreter.save_screenshot());
+    globals.variables = interpreter.variables;
+    globals.option_base = interpreter.option_base;
+
+    result
+}
+
+pub fn interpret(program: &ast::Program, config: &Config) -> Result<String, SbasicError> {
+    run(program, 0, config, &mut Globals::default())?;
 
     Ok(String::from("PROGRAM ran successfully."))
 }
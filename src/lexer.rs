@@ -1,147 +1,423 @@
+use crate::builtins;
+use crate::error::{ErrorCode, SbasicError, Span};
 use crate::token;
 
-use itertools::Itertools;
 use std::str::FromStr;
 
+// Keywords and builtin functions ignore case either way, Fold also upper-cases variable names and labels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdentifierCase {
+    #[default]
+    Fold,
+    Sensitive,
+}
+
 pub struct Instruction {
     pub line_number: u32,
+    pub label: Option<(u32, String)>,
     pub tokens: Vec<(u32, token::Token)>,
 }
 
-fn is_valid(token_string: &str) -> bool {
-    let mut characters = token_string.chars();
-    let character = characters.next();
+fn span(start: usize, end: usize) -> Span {
+    Span {
+        start: start as u32,
+        end: end as u32,
+    }
+}
+
+fn scan_while(characters: &[char], start: usize, predicate: impl Fn(char) -> bool) -> usize {
+    characters[start..]
+        .iter()
+        .position(|&character| !predicate(character))
+        .map_or(characters.len(), |length| start + length)
+}
+
+pub fn tokenize_line(line: &str, case: IdentifierCase) -> Result<Instruction, SbasicError> {
+    let characters: Vec<char> = line.chars().collect();
+
+    let (line_number, start) = match characters.first() {
+        None => (0, 0),
+        Some(character) if character.is_numeric() => {
+            let end = scan_while(&characters, 0, |character| !character.is_whitespace());
+            let number_string: String = characters[..end].iter().collect();
+
+            match u32::from_str(number_string.as_str()) {
+                Ok(number) => (number, end),
+                Err(_) => {
+                    return Err(SbasicError::lex(
+                        ErrorCode::InvalidLineNumber,
+                        "Lines must start with a number followed by a whitespace.",
+                    )
+                    .with_span(span(0, end)))
+                }
+            }
+        }
+        Some(_) => {
+            return Err(SbasicError::lex(
+                ErrorCode::InvalidLineNumber,
+                "Lines must start with a number.",
+            )
+            .with_span(Span::at(0)))
+        }
+    };
+
+    tokenize(&characters, start, line_number, case)
+}
+
+pub fn tokenize_unnumbered_line(
+    line: &str,
+    line_number: u32,
+    case: IdentifierCase,
+) -> Result<Instruction, SbasicError> {
+    let start = line.len() - line.trim_start().len();
+    if line[start..].starts_with(|character: char| character.is_ascii_digit()) {
+        let start = line[..start].chars().count() as u32;
+        return Err(SbasicError::lex(
+            ErrorCode::InvalidLineNumber,
+            "Line numbers cannot be mixed with unnumbered lines.",
+        )
+        .with_span(Span::at(start)));
+    }
+
+    tokenize(&line.chars().collect::<Vec<char>>(), 0, line_number, case)
+}
+
+// A program is numbered when its first line starts with a number.
+pub fn is_numbered(source: &str) -> bool {
+    source
+        .lines()
+        .map(|line| line.trim_start())
+        .find(|line| !line.is_empty())
+        .is_none_or(|line| line.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+// Doubled quotes inside a string stand for one quote, "SAY ""HI""".
+fn scan_string(characters: &[char], start: usize) -> Result<(token::Token, usize), SbasicError> {
+    let mut text = String::new();
+    let mut position = start + 1;
 
-    match character {
-        Some(character) => match character {
-            'a'..='z' | 'A'..='Z' => (),
-            _ => return false,
-        },
-        None => return false,
+    loop {
+        match characters.get(position) {
+            Some('"') if characters.get(position + 1) == Some(&'"') => {
+                text.push('"');
+                position += 2;
+            }
+            Some('"') => return Ok((token::Token::Text(text), position + 1)),
+            Some(character) => {
+                text.push(*character);
+                position += 1;
+            }
+            None => {
+                return Err(
+                    SbasicError::lex(ErrorCode::UnterminatedString, "Unterminated string.")
+                        .with_span(span(start, position)),
+                )
+            }
+        }
     }
+}
+
+fn scan_number(characters: &[char], start: usize) -> Result<(token::Token, usize), SbasicError> {
+    let is_digit = |character: char| character.is_ascii_digit();
 
-    for character in characters {
-        match character {
-            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => (),
-            _ => return false,
+    let mut end = scan_while(characters, start, is_digit);
+    if characters.get(end) == Some(&'.') {
+        end = scan_while(characters, end + 1, is_digit);
+    }
+    if let Some('e' | 'E') = characters.get(end) {
+        let digits = match characters.get(end + 1) {
+            Some('+' | '-') => end + 2,
+            _ => end + 1,
+        };
+        if characters
+            .get(digits)
+            .is_some_and(|&character| is_digit(character))
+        {
+            end = scan_while(characters, digits, is_digit);
         }
     }
 
-    true
+    let number_string: String = characters[start..end].iter().collect();
+    match f64::from_str(number_string.as_str()) {
+        Ok(number) => Ok((token::Token::Number(number), end)),
+        Err(_) => Err(SbasicError::lex(
+            ErrorCode::InvalidNumber,
+            format!("Invalid number {}.", number_string),
+        )
+        .with_span(span(start, end))),
+    }
+}
+
+fn scan_name(characters: &[char], start: usize) -> usize {
+    let end = scan_while(characters, start, |character| {
+        character.is_ascii_alphanumeric() || character == '_'
+    });
+    if characters.get(end) == Some(&'$') {
+        end + 1
+    } else {
+        end
+    }
+}
+
+fn variable_name(name: String, case: IdentifierCase) -> String {
+    let folded = name.to_ascii_uppercase();
+    if case == IdentifierCase::Fold || builtins::is_builtin(&folded) {
+        folded
+    } else {
+        name
+    }
 }
 
-pub fn tokenize_line(line: &str) -> Result<Instruction, String> {
-    let mut char_iterator = line.chars().enumerate().peekable();
-    let mut line_number = 0u32;
+fn scan_operator(characters: &[char], start: usize) -> Result<(token::Token, usize), SbasicError> {
+    let pair: String = characters[start..characters.len().min(start + 2)]
+        .iter()
+        .collect();
+    if pair.chars().count() == 2 {
+        if let Some(operator) = token::Token::to_token(pair.as_str()) {
+            return Ok((operator, start + 2));
+        }
+    }
+
+    let symbol = characters[start].to_string();
+    match token::Token::to_token(symbol.as_str()) {
+        Some(operator) => Ok((operator, start + 1)),
+        None => Err(SbasicError::lex(
+            ErrorCode::InvalidToken,
+            format!("Unimplemented token {}.", symbol),
+        )
+        .with_span(Span::at(start as u32))),
+    }
+}
+
+fn scan_comment(characters: &[char], start: usize) -> (u32, token::Token) {
+    let start = match characters.get(start) {
+        Some(character) if character.is_whitespace() => start + 1,
+        _ => start,
+    };
+    let comment: String = characters[start.min(characters.len())..].iter().collect();
+    (start as u32, token::Token::Comment(comment))
+}
+
+fn tokenize(
+    characters: &[char],
+    start: usize,
+    line_number: u32,
+    case: IdentifierCase,
+) -> Result<Instruction, SbasicError> {
+    let mut label: Option<(u32, String)> = None;
     let mut tokens: Vec<(u32, token::Token)> = Vec::new();
+    let mut position = start;
 
-    while char_iterator.peek() != None {
-        let (position, character) = char_iterator.next().unwrap();
-
-        if position == 0 {
-            if character.is_numeric() {
-                let mut number_chars: Vec<char> = char_iterator
-                    .by_ref()
-                    .take_while(|&(_, x)| !x.is_whitespace())
-                    .map(|(_, x)| x)
-                    .collect();
-                number_chars.insert(0, character);
-                let number_string: String = number_chars.into_iter().collect();
-
-                match u32::from_str(number_string.as_str()) {
-                    Ok(number) => line_number = number,
-                    Err(_) => {
-                        return Err(format!(
-                            "Lines Must start with a number followed by a whitespace:\n\t{}",
-                            line
-                        ))
-                    }
-                }
-            } else {
-                return Err(format!("Lines must start with a number:\n\t{}", line));
+    while position < characters.len() {
+        let character = characters[position];
+        let start = position;
+
+        let (token, end) = if character.is_whitespace() {
+            position += 1;
+            continue;
+        } else if character == '"' {
+            scan_string(characters, start)
+        } else if character.is_ascii_digit()
+            || (character == '.' && characters.get(start + 1).is_some_and(char::is_ascii_digit))
+        {
+            scan_number(characters, start)
+        } else if character.is_ascii_alphabetic() {
+            let end = scan_name(characters, start);
+            let name: String = characters[start..end].iter().collect();
+            let is_target = tokens.last().is_some_and(|(_, token)| {
+                matches!(token, token::Token::Goto | token::Token::Gosub)
+            });
+
+            // A jump target is a label name even when it spells a keyword, a clear error follows later.
+            match token::Token::to_token(name.as_str()) {
+                Some(keyword) if !is_target => Ok((keyword, end)),
+                _ => Ok((token::Token::Variable(variable_name(name, case)), end)),
+            }
+        } else if character == '\'' {
+            // An apostrophe comment works like GW-BASIC's :REM.
+            if tokens
+                .last()
+                .is_some_and(|(_, token)| *token != token::Token::Colon)
+            {
+                tokens.push((start as u32, token::Token::Colon));
             }
+            tokens.push((start as u32, token::Token::Rem));
+            tokens.push(scan_comment(characters, start + 1));
+            break;
         } else {
-            match character {
-                character if character.is_whitespace() => {
-                    continue;
-                }
+            scan_operator(characters, start)
+        }
+        .map_err(|e| e.at_line(line_number))?;
+        position = end;
 
-                '"' => {
-                    let string_chars: Vec<char> = char_iterator
-                        .by_ref()
-                        .take_while(|&(_, x)| x != '"')
-                        .map(|(_, x)| x)
-                        .collect();
-                    let text: String = string_chars.into_iter().collect();
-                    tokens.push((position.try_into().unwrap(), token::Token::Text(text)));
-                }
-                '-' => {
-                    if !tokens.is_empty() && tokens.last().unwrap().1.is_value() {
-                        tokens.push((position.try_into().unwrap(), token::Token::Minus));
-                    } else {
-                        tokens.push((position.try_into().unwrap(), token::Token::UnaryMinus));
-                    }
-                }
-                '!' => tokens.push((position.try_into().unwrap(), token::Token::Bang)),
-                '(' => tokens.push((position.try_into().unwrap(), token::Token::Lparen)),
-                ')' => tokens.push((position.try_into().unwrap(), token::Token::Rparen)),
-                ',' => tokens.push((position.try_into().unwrap(), token::Token::Comma)),
-                _ => {
-                    let mut token_chars: Vec<char> = char_iterator
-                        .by_ref()
-                        .peeking_take_while(|&(_, x)| !(x.is_whitespace() || x == ')'))
-                        .map(|(_, x)| x)
-                        .collect();
-                    token_chars.insert(0, character);
-                    let token_string: String = token_chars.into_iter().collect();
-
-                    if i32::from_str(token_string.as_str()).is_ok() {
-                        tokens.push((
-                            position.try_into().unwrap(),
-                            token::Token::Number(i32::from_str(token_string.as_str()).unwrap()),
-                        ));
-                    } else {
-                        let token = token::Token::to_token(token_string.as_str());
-
-                        match token {
-                            None => {
-                                if is_valid(&token_string) {
-                                    tokens.push((
-                                        position.try_into().unwrap(),
-                                        token::Token::Variable(token_string.to_string()),
-                                    ))
-                                } else {
-                                    return Err(format!(
-                                        "Unimplimented token at {}: \t{}",
-                                        position, token_string
-                                    ));
-                                }
-                            }
-
-                            Some(token::Token::Rem) => {
-                                tokens.push((position.try_into().unwrap(), token::Token::Rem));
-                                char_iterator.next();
-
-                                let comment_string: String =
-                                    char_iterator.by_ref().map(|(_, x)| x).collect();
-                                tokens.push((
-                                    (position + 4) as u32,
-                                    token::Token::Comment(comment_string),
-                                ));
-                            }
-
-                            Some(token) => {
-                                tokens.push((position.try_into().unwrap(), token));
-                            }
-                        }
-                    }
-                }
+        match token {
+            token::Token::Rem => {
+                tokens.push((start as u32, token::Token::Rem));
+                tokens.push(scan_comment(characters, end));
+                break;
             }
+            token::Token::Minus => {
+                let is_binary = tokens
+                    .last()
+                    .is_some_and(|(_, token)| token.is_value() || *token == token::Token::Rparen);
+                let minus = if is_binary {
+                    token::Token::Minus
+                } else {
+                    token::Token::UnaryMinus
+                };
+                tokens.push((start as u32, minus));
+            }
+            token::Token::Colon => match tokens.as_slice() {
+                [(label_position, token::Token::Variable(name))] if label.is_none() => {
+                    label = Some((*label_position, name.clone()));
+                    tokens.clear();
+                }
+                _ => tokens.push((start as u32, token::Token::Colon)),
+            },
+            token => tokens.push((start as u32, token)),
         }
     }
 
     Ok(Instruction {
         line_number,
+        label,
         tokens,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(line: &str) -> Vec<token::Token> {
+        let instruction = tokenize_line(line, IdentifierCase::Fold).unwrap();
+        instruction
+            .tokens
+            .into_iter()
+            .map(|(_, token)| token)
+            .collect()
+    }
+
+    fn label(line: &str) -> Option<String> {
+        let instruction = tokenize_line(line, IdentifierCase::Fold).unwrap();
+        instruction.label.map(|(_, label)| label)
+    }
+
+    fn variable(name: &str) -> token::Token {
+        token::Token::Variable(String::from(name))
+    }
+
+    #[test]
+    fn operators_need_no_spaces() {
+        assert_eq!(
+            tokens("10 PRINT X+1"),
+            [
+                token::Token::Print,
+                variable("X"),
+                token::Token::Plus,
+                token::Token::Number(1.0)
+            ]
+        );
+        assert_eq!(
+            tokens("10 IF A<=B THEN 20"),
+            [
+                token::Token::If,
+                variable("A"),
+                token::Token::LessThanEqual,
+                variable("B"),
+                token::Token::Then,
+                token::Token::Number(20.0)
+            ]
+        );
+        assert_eq!(
+            tokens("10 PRINT \"a\"+NAME$"),
+            [
+                token::Token::Print,
+                token::Token::Text(String::from("a")),
+                token::Token::Plus,
+                variable("NAME$")
+            ]
+        );
+    }
+
+    #[test]
+    fn doubled_quotes_escape_a_quote() {
+        assert_eq!(
+            tokens("10 PRINT \"say \"\"hi\"\"\""),
+            [
+                token::Token::Print,
+                token::Token::Text(String::from("say \"hi\""))
+            ]
+        );
+        assert_eq!(
+            tokens("10 PRINT \"\""),
+            [token::Token::Print, token::Token::Text(String::new())]
+        );
+        assert!(tokenize_line("10 PRINT \"open", IdentifierCase::Fold).is_err());
+    }
+
+    #[test]
+    fn apostrophe_starts_a_comment() {
+        assert_eq!(
+            tokens("10 PRINT 1' it's a note"),
+            [
+                token::Token::Print,
+                token::Token::Number(1.0),
+                token::Token::Colon,
+                token::Token::Rem,
+                token::Token::Comment(String::from("it's a note"))
+            ]
+        );
+    }
+
+    #[test]
+    fn tokens_keep_their_columns() {
+        let instruction = tokenize_line("10 PRINT X+12 <> 3", IdentifierCase::Fold).unwrap();
+        let columns: Vec<u32> = instruction
+            .tokens
+            .iter()
+            .map(|(column, _)| *column)
+            .collect();
+        assert_eq!(columns, [3, 9, 10, 11, 14, 17]);
+
+        let instruction = tokenize_line("20 again: PRINT 1", IdentifierCase::Fold).unwrap();
+        assert_eq!(instruction.label, Some((3, String::from("AGAIN"))));
+        assert_eq!(instruction.tokens[0].0, 10);
+    }
+
+    #[test]
+    fn keywords_before_a_colon_stay_keywords() {
+        for (line, keyword) in [
+            ("30 NEXT:PRINT \"done\"", token::Token::Next),
+            ("10 END:PRINT 1", token::Token::End),
+            ("40 ELSE: PRINT 1", token::Token::Else),
+            ("50 loop: PRINT 1", token::Token::Loop),
+        ] {
+            assert_eq!(label(line), None, "{}", line);
+            assert_eq!(
+                tokens(line)[..2],
+                [keyword, token::Token::Colon],
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn names_before_a_colon_are_labels() {
+        assert_eq!(label("20 draw: PRINT 1"), Some(String::from("DRAW")));
+        assert_eq!(label("10 again : PRINT 1"), Some(String::from("AGAIN")));
+        assert_eq!(tokens("20 draw: PRINT 1")[0], token::Token::Print);
+    }
+
+    #[test]
+    fn jump_targets_are_names() {
+        assert_eq!(
+            tokens("10 GOTO loop"),
+            [
+                token::Token::Goto,
+                token::Token::Variable(String::from("LOOP"))
+            ]
+        );
+    }
+}
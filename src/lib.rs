@@ -1,6 +1,13 @@
+pub mod ast;
+pub mod builtins;
+pub mod checker;
+pub mod error;
+pub mod formatter;
+pub mod image;
 pub mod interpreter;
 pub mod lexer;
 pub mod parser;
 pub mod renderer;
+pub mod repl;
 pub mod token;
 pub mod value_type;
@@ -1,7 +1,12 @@
+use sbasic::checker;
+use sbasic::error;
 use sbasic::interpreter;
 use sbasic::lexer;
+use sbasic::parser;
+use sbasic::repl;
 
 use std::env;
+use std::process::ExitCode;
 
 fn read_file(path: &str) -> Result<String, std::io::Error> {
     use std::fs::File;
@@ -14,31 +19,149 @@ fn read_file(path: &str) -> Result<String, std::io::Error> {
     Ok(buffer)
 }
 
-fn main() {
-    let mut argv = env::args();
-    if env::args().len() != 2 {
-        println!("Usage: sBASIC [FILE]");
-    } else {
-        let file_name: String = argv.nth(1).unwrap();
-        let program = read_file(file_name.as_str());
-        match program {
-            Ok(source) => {
-                let mut instructions: Vec<lexer::Instruction> = Vec::new();
-                for (line_number, line) in source.lines().enumerate() {
-                    let tokenized_line = lexer::tokenize_line(line);
-
-                    match tokenized_line {
-                        Ok(instruction) => instructions.push(instruction),
-                        Err(err) => println!("Error at line {}: {}", line_number, err),
-                    }
-                }
+fn run_file(source: &str, config: &interpreter::Config, check_only: bool) -> ExitCode {
+    let file_lines: Vec<&str> = source.lines().collect();
+    let source_line = |error: &error::SbasicError| {
+        error
+            .file_line()
+            .and_then(|file_line| file_lines.get(file_line as usize - 1).copied())
+    };
+
+    let program = match parser::parse_source(source, config.identifier_case) {
+        Ok(program) => program,
+        Err(errors) => {
+            for error in errors.iter() {
+                println!("{}", error.render(source_line(error)));
+            }
+            println!(
+                "Failed to run program: {} error(s) found, nothing was run.",
+                errors.len()
+            );
+            return ExitCode::FAILURE;
+        }
+    };
 
-                match interpreter::interpret(instructions) {
-                    Ok(output) => println!("{}", output),
-                    Err(error) => println!("Failed to run program: {}", error),
+    let line_numbers = parser::file_lines(source);
+    let locate = |error: error::SbasicError| match error
+        .line_number()
+        .and_then(|line_number| line_numbers.get(&line_number))
+    {
+        Some(file_line) => error.at_file_line(*file_line),
+        None => error,
+    };
+
+    let report = checker::check(&program);
+    if check_only {
+        for warning in report.warnings.iter().cloned().map(locate) {
+            println!("{}", warning.render(source_line(&warning)));
+        }
+    }
+    for error in report.errors.iter().cloned().map(locate) {
+        println!("{}", error.render(source_line(&error)));
+    }
+    if check_only {
+        println!(
+            "Checked program: {} error(s), {} warning(s).",
+            report.errors.len(),
+            report.warnings.len()
+        );
+        return if report.errors.is_empty() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+    if !report.errors.is_empty() {
+        println!(
+            "Failed to run program: {} error(s) found, nothing was run.",
+            report.errors.len()
+        );
+        return ExitCode::FAILURE;
+    }
+
+    match interpreter::interpret(&program, config) {
+        Ok(output) => {
+            println!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            let error = locate(error);
+            println!(
+                "Failed to run program: {}",
+                error.render(source_line(&error))
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_arguments() -> Option<(Option<String>, interpreter::Config, bool)> {
+    let mut config = interpreter::Config::default();
+    let mut check_only = false;
+    let mut file_name: Option<String> = None;
+    let mut argv = env::args().skip(1);
+
+    while let Some(argument) = argv.next() {
+        match argument.as_str() {
+            "--max-gosub-depth" => match argv.next().map(|depth| depth.parse::<usize>()) {
+                Some(Ok(depth)) => config.max_gosub_depth = depth,
+                _ => return None,
+            },
+            "--max-call-depth" => match argv.next().map(|depth| depth.parse::<usize>()) {
+                Some(Ok(depth)) => config.max_call_depth = depth,
+                _ => return None,
+            },
+            "--screenshot" => match argv.next() {
+                Some(path) => config.screenshot = Some(path),
+                None => return None,
+            },
+            "--frames" => match argv.next().map(|frames| frames.parse::<usize>()) {
+                Some(Ok(frames)) if frames > 0 => config.frames = Some(frames),
+                _ => return None,
+            },
+            "--fps" => match argv.next().map(|fps| fps.parse::<u32>()) {
+                Some(Ok(fps)) if fps > 0 => config.fps = Some(fps),
+                _ => return None,
+            },
+            "--vsync" => config.vsync = true,
+            "--check" => check_only = true,
+            "--headless" => config.headless = true,
+            "--case-sensitive" => config.identifier_case = lexer::IdentifierCase::Sensitive,
+            _ if file_name.is_none() => file_name = Some(argument),
+            _ => return None,
+        }
+    }
+
+    if check_only && file_name.is_none() {
+        return None;
+    }
+    Some((file_name, config, check_only))
+}
+
+fn main() -> ExitCode {
+    match parse_arguments() {
+        None => {
+            println!(
+                "Usage: sBASIC [--check] [--headless] [--screenshot FILE] [--frames N] [--fps N] [--vsync] [--case-sensitive] [--max-gosub-depth N] [--max-call-depth N] [FILE]"
+            );
+            ExitCode::FAILURE
+        }
+        Some((None, config, _)) => match repl::run(&config) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                println!("ERR: {}", error);
+                ExitCode::FAILURE
+            }
+        },
+        Some((Some(file_name), config, check_only)) => {
+            let program = read_file(file_name.as_str());
+            match program {
+                Ok(source) => run_file(&source, &config, check_only),
+                Err(error) => {
+                    println!("ERR: {}", error);
+                    ExitCode::FAILURE
                 }
             }
-            Err(error) => println!("ERR: {}", error),
         }
     }
 }
@@ -1,172 +1,1009 @@
+use crate::ast;
+use crate::builtins;
+use crate::error::{ErrorCode, SbasicError, Span};
+use crate::lexer;
 use crate::token;
-use crate::value_type;
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap};
 use std::iter::Peekable;
 use std::slice::Iter;
 
-pub fn parse(
-    token_iter: &mut Peekable<Iter<'_, (u32, token::Token)>>,
-) -> Result<VecDeque<token::Token>, String> {
-    let mut output_queue: VecDeque<token::Token> = VecDeque::new();
-    let mut operator_stack: Vec<token::Token> = Vec::new();
+type TokenIter<'a> = Peekable<Iter<'a, (u32, token::Token)>>;
+
+fn expect(token_iter: &mut TokenIter, expected: token::Token) -> bool {
+    match token_iter.peek() {
+        Some((_, token)) if *token == expected => {
+            token_iter.next();
+            true
+        }
+        _ => false,
+    }
+}
+
+fn at_statement_end(token_iter: &mut TokenIter) -> bool {
+    matches!(
+        token_iter.peek(),
+        None | Some((_, token::Token::Else)) | Some((_, token::Token::Colon))
+    )
+}
+
+fn parse_operand(token_iter: &mut TokenIter) -> Result<ast::Expr, SbasicError> {
+    match token_iter.next() {
+        Some((_, token::Token::Number(number))) => Ok(ast::Expr::Number(*number)),
+        Some((_, token::Token::Text(text))) => Ok(ast::Expr::Text(text.clone())),
+        Some((position, token::Token::Variable(name))) => {
+            let span = Span {
+                start: *position,
+                end: *position + name.chars().count() as u32,
+            };
+            match token_iter.peek() {
+                Some((_, token::Token::Lparen)) => Ok(ast::Expr::Call(
+                    name.clone(),
+                    parse_arguments(token_iter)?,
+                    span,
+                )),
+                _ if builtins::accepts_no_arguments(name) => {
+                    Ok(ast::Expr::Call(name.clone(), Vec::new(), span))
+                }
+                _ => Ok(ast::Expr::Variable(name.clone())),
+            }
+        }
+        Some((_, token::Token::UnaryMinus)) | Some((_, token::Token::Minus)) => {
+            let precedence = token::Token::UnaryMinus.operator_precedence()?;
+            let operand = parse_binary(token_iter, precedence)?;
+            Ok(ast::Expr::Unary(
+                token::Token::UnaryMinus,
+                Box::new(operand),
+            ))
+        }
+        Some((_, operator @ (token::Token::Bang | token::Token::Not))) => {
+            let precedence = operator.operator_precedence()?;
+            let operand = parse_binary(token_iter, precedence)?;
+            Ok(ast::Expr::Unary(operator.clone(), Box::new(operand)))
+        }
+        Some((_, token::Token::Lparen)) => {
+            let expression = parse_expression(token_iter)?;
+            if !expect(token_iter, token::Token::Rparen) {
+                return Err(SbasicError::parse(
+                    ErrorCode::MismatchedParenthesis,
+                    "Mismatched parenthesis in expression.",
+                ));
+            }
+            Ok(expression)
+        }
+        Some((position, token)) => Err(SbasicError::parse(
+            ErrorCode::UnexpectedToken,
+            format!("Unexpected {:?} in expression.", token),
+        )
+        .with_span(Span::at(*position))),
+        None => Err(SbasicError::parse(
+            ErrorCode::ExpectedExpression,
+            "Expected an expression.",
+        )),
+    }
+}
+
+fn parse_binary(
+    token_iter: &mut TokenIter,
+    minimum_precedence: u8,
+) -> Result<ast::Expr, SbasicError> {
+    let mut left = parse_operand(token_iter)?;
+
+    loop {
+        let (position, operator) = match token_iter.peek() {
+            Some((position, token)) if token.is_binary_operator() => (*position, token.clone()),
+            _ => break,
+        };
+
+        let precedence = operator.operator_precedence()?;
+        if precedence < minimum_precedence {
+            break;
+        }
+        token_iter.next();
+
+        let right = match operator.operator_associativity()? {
+            token::Associativity::Left => parse_binary(token_iter, precedence + 1)?,
+            token::Associativity::Right => parse_binary(token_iter, precedence)?,
+        };
+        left = ast::Expr::Binary(
+            operator,
+            Box::new(left),
+            Box::new(right),
+            Span::at(position),
+        );
+    }
+
+    Ok(left)
+}
+
+pub fn parse_expression(token_iter: &mut TokenIter) -> Result<ast::Expr, SbasicError> {
+    parse_binary(token_iter, 0)
+}
+
+pub fn parse_arguments(token_iter: &mut TokenIter) -> Result<Vec<ast::Expr>, SbasicError> {
+    let mut arguments: Vec<ast::Expr> = Vec::new();
+
+    if !expect(token_iter, token::Token::Lparen) {
+        return Err(SbasicError::parse(
+            ErrorCode::InvalidSyntax,
+            "Expected an opening parenthesis.",
+        ));
+    }
+
+    if expect(token_iter, token::Token::Rparen) {
+        return Ok(arguments);
+    }
+
+    loop {
+        arguments.push(parse_expression(token_iter)?);
+
+        match token_iter.next() {
+            Some((_, token::Token::Comma)) => {}
+            Some((_, token::Token::Rparen)) => return Ok(arguments),
+            _ => {
+                return Err(SbasicError::parse(
+                    ErrorCode::MismatchedParenthesis,
+                    "Mismatched parenthesis in expression.",
+                ))
+            }
+        }
+    }
+}
+
+fn parse_expressions(
+    token_iter: &mut TokenIter,
+    separators: &[token::Token],
+) -> Result<Vec<ast::Expr>, SbasicError> {
+    let mut expressions = vec![parse_expression(token_iter)?];
+
+    for separator in separators {
+        if !expect(token_iter, separator.clone()) {
+            return Err(SbasicError::parse(
+                ErrorCode::InvalidSyntax,
+                format!("Expected {:?}.", separator),
+            ));
+        }
+        expressions.push(parse_expression(token_iter)?);
+    }
+
+    Ok(expressions)
+}
+
+// Shapes may end with ", FILL" to draw them filled.
+fn parse_fill(token_iter: &mut TokenIter) -> Result<bool, SbasicError> {
+    if !expect(token_iter, token::Token::Comma) {
+        return Ok(false);
+    }
+    if expect(token_iter, token::Token::Fill) {
+        Ok(true)
+    } else {
+        Err(SbasicError::parse(
+            ErrorCode::InvalidSyntax,
+            "Expected FILL.",
+        ))
+    }
+}
+
+fn parse_polygon(token_iter: &mut TokenIter) -> Result<ast::Stmt, SbasicError> {
+    let mut coordinates = vec![parse_expression(token_iter)?];
+    let mut fill = false;
+    while expect(token_iter, token::Token::Comma) {
+        if expect(token_iter, token::Token::Fill) {
+            fill = true;
+            break;
+        }
+        coordinates.push(parse_expression(token_iter)?);
+    }
+
+    if coordinates.len() < 6 || coordinates.len() % 2 != 0 {
+        return Err(SbasicError::parse(
+            ErrorCode::InvalidSyntax,
+            "POLYGON expects at least three x, y points.",
+        ));
+    }
+
+    let mut points = Vec::new();
+    let mut coordinates = coordinates.into_iter();
+    while let (Some(x), Some(y)) = (coordinates.next(), coordinates.next()) {
+        points.push((x, y));
+    }
+    Ok(ast::Stmt::Polygon { points, fill })
+}
+
+fn parse_line_number(token_iter: &mut TokenIter) -> Option<u32> {
+    match token_iter.next() {
+        Some((_, token::Token::Number(number))) if *number >= 0.0 && number.fract() == 0.0 => {
+            Some(*number as u32)
+        }
+        _ => None,
+    }
+}
+
+fn parse_variable(token_iter: &mut TokenIter) -> Option<String> {
+    match token_iter.next() {
+        Some((_, token::Token::Variable(name))) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+fn parse_parameters(token_iter: &mut TokenIter) -> Result<Vec<String>, SbasicError> {
+    let mut parameters: Vec<String> = Vec::new();
+
+    if !expect(token_iter, token::Token::Lparen) || expect(token_iter, token::Token::Rparen) {
+        return Ok(parameters);
+    }
 
     loop {
-        match token_iter.peek() {
-            Some(&&(_, token::Token::Then)) | None => break,
-            _ => {}
+        match parse_variable(token_iter) {
+            Some(parameter) if parameters.contains(&parameter) => {
+                return Err(SbasicError::parse(
+                    ErrorCode::DuplicateDefinition,
+                    format!("Duplicate parameter {}.", parameter),
+                ))
+            }
+            Some(parameter) => parameters.push(parameter),
+            None => {
+                return Err(SbasicError::parse(
+                    ErrorCode::InvalidSyntax,
+                    "Parameters must be identifiers.",
+                ))
+            }
         }
 
         match token_iter.next() {
-            Some(&(_, ref value_token)) if value_token.is_value() => {
-                output_queue.push_back(value_token.clone())
-            }
-            Some(&(_, ref operator_token)) if operator_token.is_operator() => {
-                if !operator_stack.is_empty() {
-                    let top_operator = operator_stack.last().unwrap().clone();
-                    if top_operator.is_operator() {
-                        let associativity = operator_token.operator_associativity().unwrap();
-
-                        if (associativity == token::Associativity::Left
-                            && operator_token.operator_precedence()
-                                <= top_operator.operator_precedence())
-                            || (associativity == token::Associativity::Right
-                                && operator_token.operator_precedence()
-                                    < top_operator.operator_precedence())
-                        {
-                            let top_operator = operator_stack.pop().unwrap();
-                            output_queue.push_back(top_operator.clone());
-                        }
-                    }
+            Some((_, token::Token::Comma)) => {}
+            Some((_, token::Token::Rparen)) => return Ok(parameters),
+            _ => {
+                return Err(SbasicError::parse(
+                    ErrorCode::MismatchedParenthesis,
+                    "Mismatched parenthesis in parameters.",
+                ))
+            }
+        }
+    }
+}
+
+fn parse_procedure_header(
+    token_iter: &mut TokenIter,
+) -> Result<(String, Vec<String>), SbasicError> {
+    match parse_variable(token_iter) {
+        Some(name) => Ok((name, parse_parameters(token_iter)?)),
+        None => Err(SbasicError::parse(
+            ErrorCode::InvalidSyntax,
+            "SUB and FUNCTION must be followed by a name.",
+        )),
+    }
+}
+
+fn parse_let(token_iter: &mut TokenIter) -> Result<ast::Stmt, SbasicError> {
+    let name = match parse_variable(token_iter) {
+        Some(name) => name,
+        None => {
+            return Err(SbasicError::parse(
+                ErrorCode::InvalidSyntax,
+                "Invalid syntax for LET.",
+            ))
+        }
+    };
+
+    let subscripts = match token_iter.peek() {
+        Some((_, token::Token::Lparen)) => Some(parse_arguments(token_iter)?),
+        _ => None,
+    };
+
+    if !expect(token_iter, token::Token::Equals) {
+        return Err(SbasicError::parse(
+            ErrorCode::InvalidSyntax,
+            "Invalid syntax for LET.",
+        ));
+    }
+
+    Ok(ast::Stmt::Let {
+        name,
+        subscripts,
+        value: parse_expression(token_iter)?,
+    })
+}
+
+fn parse_dim(token_iter: &mut TokenIter) -> Result<ast::Stmt, SbasicError> {
+    let mut arrays: Vec<(String, Vec<ast::Expr>)> = Vec::new();
+
+    loop {
+        match parse_variable(token_iter) {
+            Some(name) => arrays.push((name, parse_arguments(token_iter)?)),
+            None => {
+                return Err(SbasicError::parse(
+                    ErrorCode::InvalidSyntax,
+                    "Invalid syntax for DIM.",
+                ))
+            }
+        }
+
+        if !expect(token_iter, token::Token::Comma) {
+            return Ok(ast::Stmt::Dim(arrays));
+        }
+    }
+}
+
+fn parse_for(token_iter: &mut TokenIter) -> Result<ast::Stmt, SbasicError> {
+    let variable = match parse_variable(token_iter) {
+        Some(variable) if expect(token_iter, token::Token::Equals) => variable,
+        _ => {
+            return Err(SbasicError::parse(
+                ErrorCode::InvalidSyntax,
+                "Invalid syntax for FOR.",
+            ))
+        }
+    };
+
+    let start = parse_expression(token_iter)?;
+    if !expect(token_iter, token::Token::To) {
+        return Err(SbasicError::parse(
+            ErrorCode::InvalidSyntax,
+            "FOR must have a valid TO limit.",
+        ));
+    }
+    let limit = parse_expression(token_iter)?;
+
+    let step = if expect(token_iter, token::Token::Step) {
+        Some(parse_expression(token_iter)?)
+    } else {
+        None
+    };
+
+    Ok(ast::Stmt::For {
+        variable,
+        start,
+        limit,
+        step,
+    })
+}
+
+fn parse_def(token_iter: &mut TokenIter) -> Result<ast::Stmt, SbasicError> {
+    let name = match parse_variable(token_iter) {
+        Some(name) if name.to_ascii_uppercase().starts_with("FN") => name,
+        _ => {
+            return Err(SbasicError::parse(
+                ErrorCode::InvalidSyntax,
+                "DEF must be followed by a name starting with FN.",
+            ))
+        }
+    };
+
+    let parameters = parse_parameters(token_iter)?;
+    if !expect(token_iter, token::Token::Equals) {
+        return Err(SbasicError::parse(
+            ErrorCode::InvalidSyntax,
+            "DEF must be followed by = and an expression.",
+        ));
+    }
+
+    Ok(ast::Stmt::Def {
+        name,
+        parameters,
+        body: parse_expression(token_iter)?,
+    })
+}
+
+// Like GW-BASIC, every statement up to ELSE or the end of the line belongs to the branch.
+fn parse_branch(token_iter: &mut TokenIter) -> Result<Vec<ast::Stmt>, SbasicError> {
+    let mut statements: Vec<ast::Stmt> = Vec::new();
+
+    loop {
+        let statement = match token_iter.next() {
+            Some((_, token::Token::Number(number)))
+                if statements.is_empty() && *number >= 0.0 && number.fract() == 0.0 =>
+            {
+                ast::Stmt::Goto(*number as u32)
+            }
+            Some((_, keyword)) => parse_keyword(keyword, token_iter)?,
+            None => {
+                return Err(SbasicError::parse(
+                    ErrorCode::InvalidSyntax,
+                    "Expected a statement after THEN or ELSE.",
+                ))
+            }
+        };
+        statements.push(check_branch_statement(statement)?);
+
+        if !expect(token_iter, token::Token::Colon) {
+            return Ok(statements);
+        }
+        if matches!(token_iter.peek(), None | Some((_, token::Token::Else))) {
+            return Ok(statements);
+        }
+    }
+}
+
+fn check_branch_statement(statement: ast::Stmt) -> Result<ast::Stmt, SbasicError> {
+    match statement {
+        ast::Stmt::IfBlock(_)
+        | ast::Stmt::ElseIf(_)
+        | ast::Stmt::Else
+        | ast::Stmt::EndIf
+        | ast::Stmt::For { .. }
+        | ast::Stmt::Next(_)
+        | ast::Stmt::While(_)
+        | ast::Stmt::Wend
+        | ast::Stmt::Do(_)
+        | ast::Stmt::Loop(_)
+        | ast::Stmt::Sub { .. }
+        | ast::Stmt::Function { .. }
+        | ast::Stmt::EndSub
+        | ast::Stmt::EndFunction => Err(SbasicError::parse(
+            ErrorCode::InvalidSyntax,
+            "Block statements cannot be used in a single line IF.",
+        )),
+        statement => Ok(statement),
+    }
+}
+
+fn parse_if(token_iter: &mut TokenIter) -> Result<ast::Stmt, SbasicError> {
+    let condition = parse_expression(token_iter)?;
+    if !expect(token_iter, token::Token::Then) {
+        return Err(SbasicError::parse(
+            ErrorCode::InvalidSyntax,
+            "IF must be followed by THEN.",
+        ));
+    }
+
+    if matches!(token_iter.peek(), None | Some((_, token::Token::Colon))) {
+        return Ok(ast::Stmt::IfBlock(condition));
+    }
+
+    let then_branch = parse_branch(token_iter)?;
+    let else_branch = if expect(token_iter, token::Token::Else) {
+        Some(parse_branch(token_iter)?)
+    } else {
+        None
+    };
+
+    Ok(ast::Stmt::If {
+        condition,
+        then_branch,
+        else_branch,
+    })
+}
+
+fn parse_loop_condition(
+    token_iter: &mut TokenIter,
+) -> Result<Option<ast::LoopCondition>, SbasicError> {
+    if expect(token_iter, token::Token::While) {
+        Ok(Some(ast::LoopCondition::While(parse_expression(
+            token_iter,
+        )?)))
+    } else if expect(token_iter, token::Token::Until) {
+        Ok(Some(ast::LoopCondition::Until(parse_expression(
+            token_iter,
+        )?)))
+    } else {
+        Ok(None)
+    }
+}
+
+fn parse_shared(token_iter: &mut TokenIter) -> Result<ast::Stmt, SbasicError> {
+    let mut names: Vec<String> = Vec::new();
+
+    loop {
+        match parse_variable(token_iter) {
+            Some(name) => names.push(name),
+            None => {
+                return Err(SbasicError::parse(
+                    ErrorCode::InvalidSyntax,
+                    "SHARED must be followed by identifiers.",
+                ))
+            }
+        }
+
+        if !expect(token_iter, token::Token::Comma) {
+            return Ok(ast::Stmt::Shared(names));
+        }
+    }
+}
+
+fn parse_keyword(
+    keyword: &token::Token,
+    token_iter: &mut TokenIter,
+) -> Result<ast::Stmt, SbasicError> {
+    match *keyword {
+        token::Token::Print => Ok(ast::Stmt::Print(parse_expression(token_iter)?)),
+        token::Token::Input => match parse_variable(token_iter) {
+            Some(name) => Ok(ast::Stmt::Input(name)),
+            None => Err(SbasicError::parse(
+                ErrorCode::InvalidSyntax,
+                "INPUT must be followed by an identifier.",
+            )),
+        },
+        token::Token::Let => parse_let(token_iter),
+        token::Token::Dim => parse_dim(token_iter),
+        token::Token::Option => match (token_iter.next(), token_iter.next()) {
+            (Some((_, token::Token::Base)), Some((_, token::Token::Number(base))))
+                if *base == 0.0 || *base == 1.0 =>
+            {
+                Ok(ast::Stmt::OptionBase(*base as usize))
+            }
+            _ => Err(SbasicError::parse(
+                ErrorCode::InvalidSyntax,
+                "OPTION BASE must be followed by 0 or 1.",
+            )),
+        },
+        token::Token::Rem => match token_iter.next() {
+            Some((_, token::Token::Comment(comment))) => Ok(ast::Stmt::Rem(comment.clone())),
+            _ => Ok(ast::Stmt::Rem(String::new())),
+        },
+        token::Token::End => {
+            if expect(token_iter, token::Token::Sub) {
+                Ok(ast::Stmt::EndSub)
+            } else if expect(token_iter, token::Token::Function) {
+                Ok(ast::Stmt::EndFunction)
+            } else if expect(token_iter, token::Token::If) {
+                Ok(ast::Stmt::EndIf)
+            } else {
+                Ok(ast::Stmt::End)
+            }
+        }
+
+        token::Token::If => parse_if(token_iter),
+        token::Token::ElseIf => {
+            let condition = parse_expression(token_iter)?;
+            if !expect(token_iter, token::Token::Then) {
+                return Err(SbasicError::parse(
+                    ErrorCode::InvalidSyntax,
+                    "ELSEIF must be followed by THEN.",
+                ));
+            }
+            Ok(ast::Stmt::ElseIf(condition))
+        }
+        token::Token::Else => Ok(ast::Stmt::Else),
+        token::Token::Goto => match parse_line_number(token_iter) {
+            Some(target) => Ok(ast::Stmt::Goto(target)),
+            None => Err(SbasicError::parse(
+                ErrorCode::InvalidSyntax,
+                "GOTO must be followed by a valid line number.",
+            )),
+        },
+        token::Token::Gosub => match parse_line_number(token_iter) {
+            Some(target) => Ok(ast::Stmt::Gosub(target)),
+            None => Err(SbasicError::parse(
+                ErrorCode::InvalidSyntax,
+                "GOSUB must be followed by a valid line number.",
+            )),
+        },
+        token::Token::Return => Ok(ast::Stmt::Return),
+        token::Token::For => parse_for(token_iter),
+        token::Token::Next => {
+            if at_statement_end(token_iter) {
+                return Ok(ast::Stmt::Next(None));
+            }
+
+            match parse_variable(token_iter) {
+                Some(variable) => Ok(ast::Stmt::Next(Some(variable))),
+                None => Err(SbasicError::parse(
+                    ErrorCode::InvalidSyntax,
+                    "Invalid syntax for NEXT.",
+                )),
+            }
+        }
+        token::Token::While => Ok(ast::Stmt::While(parse_expression(token_iter)?)),
+        token::Token::Wend => Ok(ast::Stmt::Wend),
+        token::Token::Do => Ok(ast::Stmt::Do(parse_loop_condition(token_iter)?)),
+        token::Token::Loop => Ok(ast::Stmt::Loop(parse_loop_condition(token_iter)?)),
+        token::Token::Randomize => {
+            if at_statement_end(token_iter) {
+                Ok(ast::Stmt::Randomize(None))
+            } else {
+                Ok(ast::Stmt::Randomize(Some(parse_expression(token_iter)?)))
+            }
+        }
+
+        token::Token::Def => parse_def(token_iter),
+        token::Token::Sub => {
+            let (name, parameters) = parse_procedure_header(token_iter)?;
+            Ok(ast::Stmt::Sub { name, parameters })
+        }
+        token::Token::Function => {
+            let (name, parameters) = parse_procedure_header(token_iter)?;
+            Ok(ast::Stmt::Function { name, parameters })
+        }
+        token::Token::Exit => {
+            if expect(token_iter, token::Token::Sub) {
+                Ok(ast::Stmt::ExitSub)
+            } else if expect(token_iter, token::Token::Function) {
+                Ok(ast::Stmt::ExitFunction)
+            } else if expect(token_iter, token::Token::Do) {
+                Ok(ast::Stmt::ExitDo)
+            } else if expect(token_iter, token::Token::For) {
+                Ok(ast::Stmt::ExitFor)
+            } else {
+                Err(SbasicError::parse(
+                    ErrorCode::InvalidSyntax,
+                    "Invalid syntax for EXIT.",
+                ))
+            }
+        }
+        token::Token::Call => {
+            let name = match parse_variable(token_iter) {
+                Some(name) => name,
+                None => {
+                    return Err(SbasicError::parse(
+                        ErrorCode::InvalidSyntax,
+                        "CALL must be followed by a SUB name.",
+                    ))
                 }
+            };
+
+            let arguments = if at_statement_end(token_iter) {
+                Vec::new()
+            } else {
+                parse_arguments(token_iter)?
+            };
+            Ok(ast::Stmt::Call { name, arguments })
+        }
+        token::Token::Shared => parse_shared(token_iter),
+
+        token::Token::Screen => {
+            let mut arguments = parse_expressions(token_iter, &[token::Token::Comma])?;
+            let height = arguments.pop().unwrap();
+            let width = arguments.pop().unwrap();
+            Ok(ast::Stmt::Screen { width, height })
+        }
+        token::Token::Clear => Ok(ast::Stmt::Clear),
+        token::Token::Color => {
+            let mut arguments =
+                parse_expressions(token_iter, &[token::Token::Comma, token::Token::Comma])?;
+            let blue = arguments.pop().unwrap();
+            let green = arguments.pop().unwrap();
+            let red = arguments.pop().unwrap();
+            Ok(ast::Stmt::Color { red, green, blue })
+        }
+        token::Token::Dot => {
+            let mut arguments = parse_expressions(token_iter, &[token::Token::Comma])?;
+            let y = arguments.pop().unwrap();
+            let x = arguments.pop().unwrap();
+            Ok(ast::Stmt::Dot { x, y })
+        }
+        token::Token::Line => {
+            let mut arguments = parse_expressions(
+                token_iter,
+                &[token::Token::Comma, token::Token::To, token::Token::Comma],
+            )?;
+            let y2 = arguments.pop().unwrap();
+            let x2 = arguments.pop().unwrap();
+            let y1 = arguments.pop().unwrap();
+            let x1 = arguments.pop().unwrap();
+            Ok(ast::Stmt::Line { x1, y1, x2, y2 })
+        }
+        token::Token::Circle => {
+            let mut arguments =
+                parse_expressions(token_iter, &[token::Token::Comma, token::Token::Comma])?;
+            let radius = arguments.pop().unwrap();
+            let y = arguments.pop().unwrap();
+            let x = arguments.pop().unwrap();
+
+            let mut angles = Vec::new();
+            let mut fill = false;
+            while expect(token_iter, token::Token::Comma) {
+                if expect(token_iter, token::Token::Fill) {
+                    fill = true;
+                    break;
+                }
+                angles.push(parse_expression(token_iter)?);
+            }
+
+            let arc = match angles.len() {
+                0 => None,
+                2 => {
+                    let end = angles.pop().unwrap();
+                    let start = angles.pop().unwrap();
+                    Some((start, end))
+                }
+                _ => {
+                    return Err(SbasicError::parse(
+                        ErrorCode::InvalidSyntax,
+                        "CIRCLE arcs expect a start and an end angle.",
+                    ))
+                }
+            };
+            Ok(ast::Stmt::Circle {
+                x,
+                y,
+                radius,
+                arc,
+                fill,
+            })
+        }
+        token::Token::Box => {
+            let mut arguments = parse_expressions(
+                token_iter,
+                &[token::Token::Comma, token::Token::To, token::Token::Comma],
+            )?;
+            let y2 = arguments.pop().unwrap();
+            let x2 = arguments.pop().unwrap();
+            let y1 = arguments.pop().unwrap();
+            let x1 = arguments.pop().unwrap();
+            let fill = parse_fill(token_iter)?;
+            Ok(ast::Stmt::Box {
+                x1,
+                y1,
+                x2,
+                y2,
+                fill,
+            })
+        }
+        token::Token::Ellipse => {
+            let mut arguments = parse_expressions(
+                token_iter,
+                &[
+                    token::Token::Comma,
+                    token::Token::Comma,
+                    token::Token::Comma,
+                ],
+            )?;
+            let radius_y = arguments.pop().unwrap();
+            let radius_x = arguments.pop().unwrap();
+            let y = arguments.pop().unwrap();
+            let x = arguments.pop().unwrap();
+            let fill = parse_fill(token_iter)?;
+            Ok(ast::Stmt::Ellipse {
+                x,
+                y,
+                radius_x,
+                radius_y,
+                fill,
+            })
+        }
+        token::Token::Polygon => parse_polygon(token_iter),
+        token::Token::Paint => {
+            let mut arguments = parse_expressions(token_iter, &[token::Token::Comma])?;
+            let y = arguments.pop().unwrap();
+            let x = arguments.pop().unwrap();
+            Ok(ast::Stmt::Paint { x, y })
+        }
+        token::Token::Flip => Ok(ast::Stmt::Flip),
+        token::Token::Sleep => Ok(ast::Stmt::Sleep(parse_expression(token_iter)?)),
+        token::Token::Wait => {
+            if expect(token_iter, token::Token::Frame) {
+                Ok(ast::Stmt::WaitFrame)
+            } else {
+                Err(SbasicError::parse(
+                    ErrorCode::InvalidSyntax,
+                    "WAIT must be followed by FRAME.",
+                ))
+            }
+        }
+        token::Token::SaveImage => Ok(ast::Stmt::SaveImage(parse_expression(token_iter)?)),
+
+        _ => Err(SbasicError::parse(
+            ErrorCode::InvalidSyntax,
+            "Invalid syntax.",
+        )),
+    }
+}
 
-                operator_stack.push(operator_token.clone());
+fn keyword_name(keyword: &token::Token) -> String {
+    format!("{:?}", keyword).to_uppercase()
+}
+
+pub fn parse_line(instruction: &lexer::Instruction) -> Result<Vec<ast::Line>, SbasicError> {
+    let mut token_iter = instruction.tokens.iter().peekable();
+    let mut lines: Vec<ast::Line> = Vec::new();
+
+    while let Some((position, keyword)) = token_iter.next() {
+        if *keyword == token::Token::Colon {
+            continue;
+        }
+
+        let statement = parse_keyword(keyword, &mut token_iter)
+            .map_err(|e| e.at(instruction.line_number, Span::at(*position)))?;
+        lines.push(ast::Line {
+            line_number: instruction.line_number,
+            position: *position,
+            statement,
+        });
+
+        match token_iter.next() {
+            None | Some((_, token::Token::Colon)) => {}
+            Some((position, token)) => {
+                return Err(SbasicError::parse(
+                    ErrorCode::UnexpectedToken,
+                    format!("Unexpected {:?} after {}.", token, keyword_name(keyword)),
+                )
+                .at(instruction.line_number, Span::at(*position)))
             }
-            Some(&(_, token::Token::Comma)) => operator_stack.push(token::Token::Comma),
-            Some(&(_, token::Token::Lparen)) => operator_stack.push(token::Token::Lparen),
-            Some(&(_, token::Token::Rparen)) => loop {
-                match operator_stack.pop() {
-                    Some(token::Token::Lparen) => break,
-                    Some(ref next_token) => output_queue.push_back(next_token.clone()),
-                    None => return Err(String::from("ERR: Mismatched parenthesis in expression.")),
+        }
+    }
+
+    if lines.is_empty() {
+        lines.push(ast::Line {
+            line_number: instruction.line_number,
+            position: 0,
+            statement: ast::Stmt::Rem(String::new()),
+        });
+    }
+
+    Ok(lines)
+}
+
+fn define_label(
+    labels: &mut HashMap<String, u32>,
+    instruction: &lexer::Instruction,
+) -> Result<(), SbasicError> {
+    let (position, label) = match &instruction.label {
+        Some(label) => label,
+        None => return Ok(()),
+    };
+
+    match labels.get(label) {
+        Some(line_number) => Err(SbasicError::parse(
+            ErrorCode::DuplicateDefinition,
+            format!(
+                "Label {} is already defined at line {}.",
+                label, line_number
+            ),
+        )
+        .at(instruction.line_number, Span::at(*position))),
+        None => {
+            labels.insert(label.clone(), instruction.line_number);
+            Ok(())
+        }
+    }
+}
+
+pub fn resolve_labels(
+    instruction: &mut lexer::Instruction,
+    labels: &dyn Fn(&str) -> Option<u32>,
+) -> Result<(), SbasicError> {
+    for index in 1..instruction.tokens.len() {
+        if !instruction.tokens[index - 1].1.is_jump() {
+            continue;
+        }
+
+        let (position, target) = &mut instruction.tokens[index];
+        if let token::Token::Variable(label) = target {
+            match labels(label) {
+                Some(line_number) => *target = token::Token::Number(line_number as f64),
+                None if token::Token::to_token(label).is_some() => {
+                    return Err(SbasicError::parse(
+                        ErrorCode::InvalidTarget,
+                        format!("{} is a keyword and cannot be used as a label.", label),
+                    )
+                    .at(instruction.line_number, Span::at(*position)))
+                }
+                None => {
+                    return Err(SbasicError::parse(
+                        ErrorCode::InvalidTarget,
+                        format!("Label {} is not defined.", label),
+                    )
+                    .at(instruction.line_number, Span::at(*position)))
                 }
-            },
-            _ => unreachable!(),
-        }
-    }
-
-    while !operator_stack.is_empty() {
-        match operator_stack.pop().unwrap() {
-            token::Token::Lparen | token::Token::Rparen => {
-                return Err(String::from("ERR: Mismatched parenthesis in expression."))
-            }
-            operator_token => output_queue.push_back(operator_token.clone()),
-        }
-    }
-
-    Ok(output_queue)
-}
-
-pub fn parse_and_eval<'a>(
-    token_iter: &mut Peekable<Iter<'a, (u32, token::Token)>>,
-    variables: &HashMap<String, value_type::ValueType>,
-) -> Result<value_type::ValueType, String> {
-    match parse(token_iter) {
-        Ok(mut output_queue) => {
-            let mut stack: Vec<value_type::ValueType> = Vec::new();
-
-            while !output_queue.is_empty() {
-                match output_queue.pop_front() {
-                    Some(token::Token::Number(ref number)) => {
-                        stack.push(value_type::ValueType::Number(*number))
-                    }
-                    Some(token::Token::Text(ref text)) => {
-                        stack.push(value_type::ValueType::Text(text.clone()))
-                    }
-                    Some(token::Token::Variable(ref name)) => match variables.get(name) {
-                        Some(value) => stack.push(value.clone()),
-                        None => {
-                            return Err(format!(
-                                "ERR: Invalid variable reference {} in expression.",
-                                name
-                            ))
-                        }
-                    },
-
-                    Some(ref unary_token) if unary_token.is_unary_operator() => {
-                        if !stack.is_empty() {
-                            let value = stack.pop().unwrap();
-                            let result = match *unary_token {
-                                token::Token::UnaryMinus => -value,
-                                token::Token::Bang => !value,
-
-                                _ => unreachable!(),
-                            };
-                            match result {
-                                Ok(value) => stack.push(value),
-                                Err(error) => return Err(error),
-                            }
-                        } else {
-                            return Err(format!("ERR: {:?} requires an operand.", unary_token));
-                        }
-                    }
-
-                    Some(ref comparison_token) if comparison_token.is_comparison_operator() => {
-                        if stack.len() >= 2 {
-                            let operand_2 = &stack.pop().unwrap();
-                            let operand_1 = &stack.pop().unwrap();
-
-                            let result = match *comparison_token {
-                                token::Token::Equals => operand_1 == operand_2,
-                                token::Token::NotEqual => operand_1 != operand_2,
-                                token::Token::LessThan => operand_1 < operand_2,
-                                token::Token::GreaterThan => operand_1 > operand_2,
-                                token::Token::LessThanEqual => operand_1 <= operand_2,
-                                token::Token::GreaterThanEqual => operand_1 >= operand_2,
-
-                                _ => unreachable!(),
-                            };
-
-                            stack.push(value_type::ValueType::Bool(result));
-                        } else {
-                            return Err(format!(
-                                "ERR: {:?} Comparison operator requires two operands",
-                                comparison_token
-                            ));
-                        }
-                    }
-
-                    Some(ref binary_operator_token)
-                        if binary_operator_token.is_binary_operator() =>
-                    {
-                        if stack.len() >= 2 {
-                            let operand_2 = stack.pop().unwrap();
-                            let operand_1 = stack.pop().unwrap();
-
-                            let result = match *binary_operator_token {
-                                token::Token::Plus => operand_1 + operand_2,
-                                token::Token::Minus => operand_1 - operand_2,
-                                token::Token::Multiply => operand_1 * operand_2,
-                                token::Token::Divide => operand_1 / operand_2,
-
-                                _ => unreachable!(),
-                            };
-
-                            match result {
-                                Ok(value) => stack.push(value),
-                                Err(err) => return Err(err),
-                            }
-                        }
-                    }
-
-                    None => unreachable!(),
-                    _ => unreachable!(),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn parse_program(
+    mut instructions: Vec<lexer::Instruction>,
+) -> Result<ast::Program, SbasicError> {
+    let mut labels: HashMap<String, u32> = HashMap::new();
+    for instruction in instructions.iter() {
+        define_label(&mut labels, instruction)?;
+    }
+
+    let mut lines: BTreeMap<u32, Vec<ast::Line>> = BTreeMap::new();
+    for instruction in instructions.iter_mut() {
+        resolve_labels(instruction, &|label| labels.get(label).copied())?;
+        let statements = parse_line(instruction)?;
+        if lines.contains_key(&instruction.line_number) {
+            return Err(duplicate_line(instruction.line_number, None));
+        }
+        lines.insert(instruction.line_number, statements);
+    }
+
+    Ok(ast::Program {
+        lines: lines.into_values().flatten().collect(),
+    })
+}
+
+fn duplicate_line(line_number: u32, first_file_line: Option<u32>) -> SbasicError {
+    let message = match first_file_line {
+        Some(file_line) => format!(
+            "Line {} is already defined at file line {}.",
+            line_number, file_line
+        ),
+        None => format!("Line {} is already defined.", line_number),
+    };
+
+    SbasicError::parse(ErrorCode::DuplicateLineNumber, message).at(line_number, Span::at(0))
+}
+
+// Unnumbered programs use their file lines as line numbers.
+fn tokenize_source(
+    source: &str,
+    case: lexer::IdentifierCase,
+) -> Vec<(u32, Result<lexer::Instruction, SbasicError>)> {
+    let numbered = lexer::is_numbered(source);
+
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, text)| !text.trim().is_empty())
+        .map(|(index, text)| {
+            let file_line = index as u32 + 1;
+            let instruction = if numbered {
+                lexer::tokenize_line(text, case)
+            } else {
+                lexer::tokenize_unnumbered_line(text, file_line, case)
+            };
+            (file_line, instruction)
+        })
+        .collect()
+}
+
+pub fn file_lines(source: &str) -> HashMap<u32, u32> {
+    let mut file_lines: HashMap<u32, u32> = HashMap::new();
+    for (file_line, instruction) in tokenize_source(source, lexer::IdentifierCase::default()) {
+        if let Ok(instruction) = instruction {
+            file_lines
+                .entry(instruction.line_number)
+                .or_insert(file_line);
+        }
+    }
+    file_lines
+}
+
+pub fn parse_source(
+    source: &str,
+    case: lexer::IdentifierCase,
+) -> Result<ast::Program, Vec<SbasicError>> {
+    let mut instructions: Vec<(u32, lexer::Instruction)> = Vec::new();
+    let mut labels: HashMap<String, u32> = HashMap::new();
+    let mut errors: Vec<SbasicError> = Vec::new();
+
+    for (file_line, instruction) in tokenize_source(source, case) {
+        match instruction {
+            Ok(instruction) => {
+                if let Err(e) = define_label(&mut labels, &instruction) {
+                    errors.push(e.at_file_line(file_line));
                 }
+                instructions.push((file_line, instruction));
+            }
+            Err(e) => errors.push(e.at_file_line(file_line)),
+        }
+    }
+
+    let mut lines: BTreeMap<u32, (u32, Vec<ast::Line>)> = BTreeMap::new();
+    for (file_line, mut instruction) in instructions {
+        let parsed = resolve_labels(&mut instruction, &|label| labels.get(label).copied())
+            .and_then(|_| parse_line(&instruction));
+        let statements = match parsed {
+            Ok(statements) => statements,
+            Err(e) => {
+                errors.push(e.at_file_line(file_line));
+                continue;
             }
+        };
 
-            assert!(stack.len() == 1);
-            Ok(stack[0].clone())
+        let line_number = instruction.line_number;
+        match lines.get(&line_number) {
+            Some((first_file_line, _)) => errors
+                .push(duplicate_line(line_number, Some(*first_file_line)).at_file_line(file_line)),
+            None => {
+                lines.insert(line_number, (file_line, statements));
+            }
         }
-        _ => Err(String::from("ERR: Invalid expression.")),
     }
+
+    if !errors.is_empty() {
+        errors.sort_by_key(|e| e.file_line());
+        return Err(errors);
+    }
+
+    Ok(ast::Program {
+        lines: lines
+            .into_values()
+            .flat_map(|(_, statements)| statements)
+            .collect(),
+    })
 }
@@ -1,67 +1,426 @@
+use crate::error::{ErrorCode, SbasicError};
+
 use sdl2::event::Event;
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
 
-use std::f32::consts::PI;
+use std::f32::consts::TAU;
 
 const DEFAULT_WIDTH: u32 = 640;
 const DEFAULT_HEIGHT: u32 = 360;
 
-pub struct Renderer {
+// The first value in low..=high + 1 at which a predicate that holds up to some point stops holding.
+fn partition_point(mut low: i64, high: i64, predicate: impl Fn(i128) -> bool) -> i64 {
+    let mut high = high + 1;
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if predicate(middle as i128) {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+// Midpoint ellipse rasterisation of one quadrant. The flat part has one point per column and the
+// steep part one point per row, both are solved directly so only visible offsets are ever visited.
+// The decision values are scaled by 4 to stay in integers and i128 keeps them exact for any i32 radius.
+struct Quadrant {
+    rx2: i128,
+    ry2: i128,
+    rx: i64,
+    ry: i64,
+    boundary_x: i64,
+    boundary_y: i64,
+}
+
+impl Quadrant {
+    fn new(rx: i64, ry: i64) -> Self {
+        let mut quadrant = Quadrant {
+            rx2: (rx as i128).pow(2),
+            ry2: (ry as i128).pow(2),
+            rx,
+            ry,
+            boundary_x: rx,
+            boundary_y: ry,
+        };
+
+        if rx == 0 {
+            quadrant.boundary_x = 0;
+        } else if ry == 0 {
+            quadrant.boundary_y = 0;
+        } else {
+            let x = partition_point(0, rx, |x| {
+                quadrant.ry2 * x < quadrant.rx2 * quadrant.y_at(x as i64) as i128
+            });
+            // The step onto the boundary column moves down one row at most.
+            quadrant.boundary_x = x;
+            quadrant.boundary_y = quadrant.y_at(x).max(quadrant.y_at(x - 1) - 1);
+        }
+        quadrant
+    }
+
+    // The row of column x in the flat part, the last one whose lower midpoint is inside.
+    fn y_at(&self, x: i64) -> i64 {
+        if self.ry == 0 {
+            return 0;
+        }
+        let limit = 4 * self.ry2 * (self.rx2 - (x as i128).pow(2));
+        let y = partition_point(0, self.ry, |y| self.rx2 * (2 * y - 1).pow(2) < limit);
+        (y - 1).max(0)
+    }
+
+    // The column of row y in the steep part, the first one whose right midpoint is outside.
+    fn x_at(&self, y: i64) -> i64 {
+        if self.rx == 0 || self.ry == 0 || y >= self.boundary_y {
+            return self.boundary_x;
+        }
+        let limit = 4 * self.rx2 * (self.ry2 - (y as i128).pow(2));
+        let x = partition_point(0, self.rx, |x| self.ry2 * (2 * x + 1).pow(2) <= limit);
+        x.max(self.boundary_x)
+    }
+
+    // The widest column on row y, used to fill.
+    fn half_width(&self, y: i64) -> i64 {
+        if y <= self.boundary_y {
+            self.x_at(y)
+        } else {
+            partition_point(0, self.boundary_x - 1, |x| self.y_at(x as i64) >= y) - 1
+        }
+    }
+}
+
+// Offsets in 0..=end that put center - offset or center + offset inside 0..limit.
+fn visible_offsets(center: i64, limit: u32, end: i64) -> Vec<(i64, i64)> {
+    let limit = limit as i64;
+    let mut ranges: Vec<(i64, i64)> = [(-center, limit - 1 - center), (center - limit + 1, center)]
+        .into_iter()
+        .map(|(first, last)| (first.max(0), last.min(end)))
+        .filter(|(first, last)| first <= last)
+        .collect();
+    ranges.sort_unstable();
+
+    if let [(_, first_last), (second_first, second_last)] = ranges[..] {
+        if second_first <= first_last + 1 {
+            ranges = vec![(ranges[0].0, first_last.max(second_last))];
+        }
+    }
+    ranges
+}
+
+// Clamps a coordinate to just outside the surface so spans stay short and fit in an i32.
+fn clip(value: i64, limit: u32) -> i32 {
+    value.clamp(-1, limit as i64) as i32
+}
+
+// Only the points that land on the surface are kept, so huge radii stay cheap.
+fn ellipse_outline(
+    (pos_x, pos_y): (i32, i32),
+    rx: i64,
+    ry: i64,
+    (width, height): (u32, u32),
+    keep: impl Fn(i64, i64) -> bool,
+) -> Vec<(i32, i32)> {
+    let (pos_x, pos_y) = (pos_x as i64, pos_y as i64);
+    let quadrant = Quadrant::new(rx, ry);
+
+    let mut points = Vec::new();
+    let mut visit = |x: i64, y: i64| {
+        for (index, (dx, dy)) in [(x, y), (-x, y), (x, -y), (-x, -y)].into_iter().enumerate() {
+            if (index & 1 == 1 && x == 0) || (index & 2 == 2 && y == 0) || !keep(dx, dy) {
+                continue;
+            }
+            let (screen_x, screen_y) = (pos_x + dx, pos_y + dy);
+            if screen_x >= 0 && screen_y >= 0 && screen_x < width as i64 && screen_y < height as i64
+            {
+                points.push((screen_x as i32, screen_y as i32));
+            }
+        }
+    };
+
+    for (first, last) in visible_offsets(pos_x, width, quadrant.boundary_x - 1) {
+        for x in first..=last {
+            visit(x, quadrant.y_at(x));
+        }
+    }
+    for (first, last) in visible_offsets(pos_y, height, quadrant.boundary_y) {
+        for y in first..=last {
+            visit(quadrant.x_at(y), y);
+        }
+    }
+    points
+}
+
+// The half width of every visible row of a filled ellipse.
+fn ellipse_spans(
+    (pos_x, pos_y): (i32, i32),
+    rx: i64,
+    ry: i64,
+    size: (u32, u32),
+) -> Vec<(i32, i64)> {
+    let quadrant = Quadrant::new(rx, ry);
+    if pos_x as i64 + rx < 0 || pos_x as i64 - rx >= size.0 as i64 {
+        return Vec::new();
+    }
+
+    (0..size.1 as i32)
+        .filter(|row| (*row as i64 - pos_y as i64).abs() <= ry)
+        .map(|row| (row, quadrant.half_width((row as i64 - pos_y as i64).abs())))
+        .collect()
+}
+
+// Angles are in radians and count counter-clockwise from the positive x axis, like QBasic.
+fn in_arc(x: i64, y: i64, start: f32, end: f32) -> bool {
+    if (end - start).abs() >= TAU {
+        return true;
+    }
+
+    let angle = (-y as f32).atan2(x as f32).rem_euclid(TAU);
+    let (start, end) = (start.rem_euclid(TAU), end.rem_euclid(TAU));
+    if start <= end {
+        start <= angle && angle <= end
+    } else {
+        angle >= start || angle <= end
+    }
+}
+
+pub trait Renderer {
+    fn set_size(&mut self, width: i32, height: i32) -> Result<(), SbasicError>;
+    fn size(&self) -> (u32, u32);
+    fn present(&mut self);
+    fn poll_events(&mut self, is_running: &mut bool);
+    fn render_clear(&mut self);
+    fn render_setcolor(&mut self, red: i32, green: i32, blue: i32);
+    fn render_dot(&mut self, x: i32, y: i32);
+    fn render_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32);
+    fn read_pixels(&mut self) -> (u32, u32, Vec<[u8; 3]>);
+
+    fn render_dots(&mut self, points: &[(i32, i32)]) {
+        for &(x, y) in points.iter() {
+            self.render_dot(x, y);
+        }
+    }
+
+    fn render_box(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, fill: bool) {
+        if fill {
+            let height = self.size().1 as i32;
+            for y in y1.min(y2).max(0)..=y1.max(y2).min(height - 1) {
+                self.render_line(x1, y, x2, y);
+            }
+        } else {
+            self.render_polygon(&[(x1, y1), (x2, y1), (x2, y2), (x1, y2)], false);
+        }
+    }
+
+    fn render_circle(&mut self, pos_x: i32, pos_y: i32, r: i32, fill: bool) {
+        self.render_ellipse(pos_x, pos_y, r, r, fill);
+    }
+
+    fn render_ellipse(&mut self, pos_x: i32, pos_y: i32, rx: i32, ry: i32, fill: bool) {
+        let (rx, ry) = (rx.unsigned_abs() as i64, ry.unsigned_abs() as i64);
+        let size = self.size();
+
+        if fill {
+            for (row, half_width) in ellipse_spans((pos_x, pos_y), rx, ry, size) {
+                let left = clip(pos_x as i64 - half_width, size.0);
+                let right = clip(pos_x as i64 + half_width, size.0);
+                self.render_line(left, row, right, row);
+            }
+            return;
+        }
+
+        let points = ellipse_outline((pos_x, pos_y), rx, ry, size, |_, _| true);
+        self.render_dots(&points);
+    }
+
+    // A filled arc is a pie slice.
+    fn render_arc(&mut self, pos_x: i32, pos_y: i32, r: i32, start: f32, end: f32, fill: bool) {
+        let r = r.unsigned_abs() as i64;
+        let size = self.size();
+
+        let points = if fill {
+            let mut points = Vec::new();
+            for (row, half_width) in ellipse_spans((pos_x, pos_y), r, r, size) {
+                let left = clip(pos_x as i64 - half_width, size.0).max(0);
+                let right = clip(pos_x as i64 + half_width, size.0).min(size.0 as i32 - 1);
+                for x in left..=right {
+                    if in_arc(
+                        x as i64 - pos_x as i64,
+                        row as i64 - pos_y as i64,
+                        start,
+                        end,
+                    ) {
+                        points.push((x, row));
+                    }
+                }
+            }
+            points
+        } else {
+            ellipse_outline((pos_x, pos_y), r, r, size, |x, y| in_arc(x, y, start, end))
+        };
+        self.render_dots(&points);
+    }
+
+    // Filled polygons use the even-odd rule, sampled through the middle of each row.
+    fn render_polygon(&mut self, points: &[(i32, i32)], fill: bool) {
+        if fill {
+            let height = self.size().1 as i32;
+            let top = points.iter().map(|&(_, y)| y).min().unwrap_or(0).max(0);
+            let bottom = points.iter().map(|&(_, y)| y).max().unwrap_or(0);
+
+            for y in top..=bottom.min(height - 1) {
+                let scan = y as f64 + 0.5;
+                let mut crossings: Vec<f64> = Vec::new();
+                for (index, &(x1, y1)) in points.iter().enumerate() {
+                    let (x1, y1) = (x1 as f64, y1 as f64);
+                    let (x2, y2) = points[(index + 1) % points.len()];
+                    let (x2, y2) = (x2 as f64, y2 as f64);
+                    if (y1 <= scan) != (y2 <= scan) {
+                        let t = (scan - y1) / (y2 - y1);
+                        crossings.push(x1 + t * (x2 - x1));
+                    }
+                }
+
+                crossings.sort_by(|a, b| a.total_cmp(b));
+                for pair in crossings.chunks_exact(2) {
+                    self.render_line(pair[0].round() as i32, y, pair[1].round() as i32, y);
+                }
+            }
+        }
+
+        for (index, &(x1, y1)) in points.iter().enumerate() {
+            let (x2, y2) = points[(index + 1) % points.len()];
+            self.render_line(x1, y1, x2, y2);
+        }
+    }
+
+    // Fills the area around (x, y) that has the same color as (x, y).
+    fn render_paint(&mut self, x: i32, y: i32) {
+        let (width, height, pixels) = self.read_pixels();
+        let inside = |x: i32, y: i32| x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height;
+        let index = |x: i32, y: i32| y as usize * width as usize + x as usize;
+        if !inside(x, y) || pixels.len() < width as usize * height as usize {
+            return;
+        }
+
+        let target = pixels[index(x, y)];
+        let mut visited = vec![false; pixels.len()];
+        let mut stack = vec![(x, y)];
+        visited[index(x, y)] = true;
+
+        let mut region = Vec::new();
+        while let Some((x, y)) = stack.pop() {
+            region.push((x, y));
+            for (next_x, next_y) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                if inside(next_x, next_y)
+                    && !visited[index(next_x, next_y)]
+                    && pixels[index(next_x, next_y)] == target
+                {
+                    visited[index(next_x, next_y)] = true;
+                    stack.push((next_x, next_y));
+                }
+            }
+        }
+        self.render_dots(&region);
+    }
+}
+
+// Nothing touches SDL until the first graphics statement, so text-only programs run without a display.
+pub fn create(headless: bool, vsync: bool) -> Result<Box<dyn Renderer>, SbasicError> {
+    if headless {
+        Ok(Box::new(HeadlessRenderer::default()))
+    } else {
+        Ok(Box::new(SdlRenderer::new(vsync)?))
+    }
+}
+
+pub struct SdlRenderer {
     canvas: sdl2::render::WindowCanvas,
     context: sdl2::Sdl,
 }
 
-impl Renderer {
-    pub fn default() -> Self {
-        let sdl_context = sdl2::init().unwrap();
-        let video_subsystem = sdl_context.video().unwrap();
+impl SdlRenderer {
+    pub fn new(vsync: bool) -> Result<Self, SbasicError> {
+        let no_display = |e: String| {
+            SbasicError::runtime(
+                ErrorCode::NoDisplay,
+                format!(
+                    "Cannot open a window ({}), use --headless to draw without one.",
+                    e
+                ),
+            )
+        };
+
+        let sdl_context = sdl2::init().map_err(no_display)?;
+        let video_subsystem = sdl_context.video().map_err(no_display)?;
 
         let mut window = video_subsystem
             .window("sBASIC", DEFAULT_WIDTH, DEFAULT_HEIGHT)
             .position_centered()
             .build()
-            .unwrap();
+            .map_err(|e| no_display(e.to_string()))?;
         window.hide();
 
-        let canvas = window.into_canvas().build().unwrap();
+        let mut builder = window.into_canvas();
+        if vsync {
+            builder = builder.present_vsync();
+        }
+        let canvas = builder.build().map_err(|e| no_display(e.to_string()))?;
 
-        Renderer {
-            canvas: canvas,
+        Ok(SdlRenderer {
+            canvas,
             context: sdl_context,
-        }
+        })
     }
+}
 
-    pub fn set_size(&mut self, width: i32, height: i32) {
+impl Renderer for SdlRenderer {
+    fn set_size(&mut self, width: i32, height: i32) -> Result<(), SbasicError> {
         let window = self.canvas.window_mut();
-        window.set_size(width as u32, height as u32).unwrap();
+        window
+            .set_size(width as u32, height as u32)
+            .map_err(|e| SbasicError::runtime(ErrorCode::IllegalFunctionCall, e.to_string()))?;
         window.show();
+        Ok(())
+    }
+
+    fn size(&self) -> (u32, u32) {
+        self.canvas.output_size().unwrap()
     }
 
-    pub fn update(&mut self, is_running: &mut bool) {
+    fn present(&mut self) {
         self.canvas.present();
+    }
 
+    fn poll_events(&mut self, is_running: &mut bool) {
         for event in self.context.event_pump().unwrap().poll_iter() {
-            match event {
-                Event::Quit { .. } => *is_running = false,
-                _ => {}
+            if let Event::Quit { .. } = event {
+                *is_running = false;
             }
         }
     }
-    pub fn render_clear(&mut self) {
+    fn render_clear(&mut self) {
         self.canvas.clear();
-        self.canvas.present();
     }
-    pub fn render_setcolor(&mut self, red: i32, green: i32, blue: i32) {
+    fn render_setcolor(&mut self, red: i32, green: i32, blue: i32) {
         self.canvas
             .set_draw_color(Color::RGB(red as u8, green as u8, blue as u8));
     }
 
-    pub fn render_dot(&mut self, x: i32, y: i32) {
+    fn render_dot(&mut self, x: i32, y: i32) {
         let point = sdl2::rect::Point::new(x, y);
         self.canvas.draw_point(point).unwrap();
     }
 
-    pub fn render_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32) {
+    fn render_dots(&mut self, points: &[(i32, i32)]) {
+        let points: Vec<sdl2::rect::Point> = points
+            .iter()
+            .map(|&(x, y)| sdl2::rect::Point::new(x, y))
+            .collect();
+        self.canvas.draw_points(points.as_slice()).unwrap();
+    }
+
+    fn render_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32) {
         let points = [
             sdl2::rect::Point::new(x1, y1),
             sdl2::rect::Point::new(x2, y2),
@@ -69,14 +428,357 @@ impl Renderer {
         self.canvas.draw_line(points[0], points[1]).unwrap();
     }
 
-    pub fn render_circle(&mut self, pos_x: i32, pos_y: i32, r: i32) {
-        let mut alpha: f32 = 0.0;
-        while alpha < 2.0 * PI {
-            let x = alpha.cos() * (r as f32) + (pos_x as f32);
-            let y = alpha.sin() * (r as f32) + (pos_y as f32);
+    fn read_pixels(&mut self) -> (u32, u32, Vec<[u8; 3]>) {
+        let (width, height) = self.canvas.output_size().unwrap();
+        let bytes = self
+            .canvas
+            .read_pixels(None, PixelFormatEnum::RGB24)
+            .unwrap();
+        let pixels = bytes
+            .chunks_exact(3)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect();
+        (width, height, pixels)
+    }
+}
+
+// Liang-Barsky, trims a line to the surface so only pixels that can be seen are walked.
+fn clip_line(
+    x1: i32,
+    y1: i32,
+    x2: i32,
+    y2: i32,
+    width: u32,
+    height: u32,
+) -> Option<(i64, i64, i64, i64)> {
+    let inside = |x: i32, y: i32| x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height;
+    if inside(x1, y1) && inside(x2, y2) {
+        return Some((x1 as i64, y1 as i64, x2 as i64, y2 as i64));
+    }
+
+    let (x1, y1, x2, y2) = (x1 as f64, y1 as f64, x2 as f64, y2 as f64);
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    let (right, bottom) = (width as f64 - 1.0, height as f64 - 1.0);
+
+    let (mut start, mut end) = (0.0f64, 1.0f64);
+    for (p, q) in [(-dx, x1), (dx, right - x1), (-dy, y1), (dy, bottom - y1)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            start = start.max(q / p);
+        } else {
+            end = end.min(q / p);
+        }
+    }
+    if start > end {
+        return None;
+    }
+
+    Some((
+        (x1 + start * dx).round() as i64,
+        (y1 + start * dy).round() as i64,
+        (x1 + end * dx).round() as i64,
+        (y1 + end * dy).round() as i64,
+    ))
+}
+
+pub struct HeadlessRenderer {
+    width: u32,
+    height: u32,
+    color: [u8; 3],
+    pixels: Vec<[u8; 3]>,
+}
+
+impl Default for HeadlessRenderer {
+    fn default() -> Self {
+        HeadlessRenderer {
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            color: [0, 0, 0],
+            pixels: vec![[0, 0, 0]; (DEFAULT_WIDTH * DEFAULT_HEIGHT) as usize],
+        }
+    }
+}
+
+impl Renderer for HeadlessRenderer {
+    fn set_size(&mut self, width: i32, height: i32) -> Result<(), SbasicError> {
+        let length = usize::try_from(width)
+            .ok()
+            .zip(usize::try_from(height).ok())
+            .and_then(|(width, height)| width.checked_mul(height))
+            .ok_or_else(|| {
+                SbasicError::runtime(
+                    ErrorCode::IllegalFunctionCall,
+                    format!("Invalid SCREEN size {} x {}.", width, height),
+                )
+            })?;
+
+        self.width = width as u32;
+        self.height = height as u32;
+        self.pixels = vec![[0, 0, 0]; length];
+        Ok(())
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn present(&mut self) {}
+
+    fn poll_events(&mut self, _is_running: &mut bool) {}
+
+    fn render_clear(&mut self) {
+        let color = self.color;
+        self.pixels.fill(color);
+    }
+
+    fn render_setcolor(&mut self, red: i32, green: i32, blue: i32) {
+        self.color = [red as u8, green as u8, blue as u8];
+    }
+
+    fn render_dot(&mut self, x: i32, y: i32) {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return;
+        }
+        self.pixels[y as usize * self.width as usize + x as usize] = self.color;
+    }
+
+    // Bresenham, both end points included like SDL's draw_line.
+    fn render_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32) {
+        let (x1, y1, x2, y2) = match clip_line(x1, y1, x2, y2, self.width, self.height) {
+            Some(line) => line,
+            None => return,
+        };
+
+        let (dx, dy) = ((x2 - x1).abs(), -(y2 - y1).abs());
+        let (step_x, step_y) = ((x2 - x1).signum(), (y2 - y1).signum());
+        let (mut x, mut y, mut error) = (x1, y1, dx + dy);
+
+        loop {
             self.render_dot(x as i32, y as i32);
+            if x == x2 && y == y2 {
+                break;
+            }
+
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    fn read_pixels(&mut self) -> (u32, u32, Vec<[u8; 3]>) {
+        (self.width, self.height, self.pixels.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            alpha += 0.001;
+    // The classic incremental midpoint ellipse, the reference Quadrant has to agree with.
+    fn incremental_quadrant(rx: i64, ry: i64) -> Vec<(i64, i64)> {
+        let mut points = Vec::new();
+        if rx == 0 || ry == 0 {
+            points.extend((0..=rx).map(|x| (x, 0)));
+            points.extend((1..=ry).map(|y| (0, y)));
+            return points;
         }
+
+        let (rx2, ry2) = (rx * rx, ry * ry);
+        let (mut x, mut y) = (0, ry);
+        let mut decision = 4 * ry2 - 4 * rx2 * y + rx2;
+        while ry2 * x < rx2 * y {
+            points.push((x, y));
+            if decision < 0 {
+                decision += 4 * ry2 * (2 * x + 3);
+            } else {
+                decision += 4 * ry2 * (2 * x + 3) + 4 * rx2 * (2 - 2 * y);
+                y -= 1;
+            }
+            x += 1;
+        }
+
+        decision = ry2 * (2 * x + 1).pow(2) - 4 * rx2 * ry2 + 4 * rx2 * (y - 1).pow(2);
+        while y >= 0 {
+            points.push((x, y));
+            if decision > 0 {
+                decision += 4 * rx2 * (3 - 2 * y);
+            } else {
+                decision += 4 * ry2 * (2 * x + 2) + 4 * rx2 * (3 - 2 * y);
+                x += 1;
+            }
+            y -= 1;
+        }
+        points
+    }
+
+    fn direct_quadrant(rx: i64, ry: i64) -> Vec<(i64, i64)> {
+        let quadrant = Quadrant::new(rx, ry);
+        let mut points: Vec<(i64, i64)> = (0..quadrant.boundary_x)
+            .map(|x| (x, quadrant.y_at(x)))
+            .collect();
+        points.extend(
+            (0..=quadrant.boundary_y)
+                .rev()
+                .map(|y| (quadrant.x_at(y), y)),
+        );
+        points.sort_unstable();
+        points
+    }
+
+    #[test]
+    fn quadrant_matches_incremental_midpoint() {
+        for rx in 0..60 {
+            for ry in 0..60 {
+                let mut expected = incremental_quadrant(rx, ry);
+                expected.sort_unstable();
+                assert_eq!(direct_quadrant(rx, ry), expected, "rx {} ry {}", rx, ry);
+            }
+        }
+        for (rx, ry) in [(1000, 7), (7, 1000), (4096, 4095), (12345, 6789)] {
+            let mut expected = incremental_quadrant(rx, ry);
+            expected.sort_unstable();
+            assert_eq!(direct_quadrant(rx, ry), expected);
+        }
+    }
+
+    fn canvas(width: i32, height: i32) -> HeadlessRenderer {
+        let mut renderer = HeadlessRenderer::default();
+        renderer.set_size(width, height).unwrap();
+        renderer.render_setcolor(255, 255, 255);
+        renderer
+    }
+
+    fn lit(renderer: &mut HeadlessRenderer) -> Vec<(i32, i32)> {
+        let (width, _, pixels) = renderer.read_pixels();
+        pixels
+            .iter()
+            .enumerate()
+            .filter(|(_, &pixel)| pixel == [255, 255, 255])
+            .map(|(index, _)| ((index as u32 % width) as i32, (index as u32 / width) as i32))
+            .collect()
+    }
+
+    #[test]
+    fn set_size_rejects_negative_sizes() {
+        let mut renderer = HeadlessRenderer::default();
+        assert!(renderer.set_size(-1, 10).is_err());
+        assert!(renderer.set_size(10, i32::MIN).is_err());
+        assert_eq!(renderer.size(), (DEFAULT_WIDTH, DEFAULT_HEIGHT));
+    }
+
+    #[test]
+    fn line_includes_both_end_points() {
+        let mut renderer = canvas(8, 8);
+        renderer.render_line(1, 1, 4, 4);
+        assert_eq!(lit(&mut renderer), vec![(1, 1), (2, 2), (3, 3), (4, 4)]);
+
+        let mut renderer = canvas(8, 8);
+        renderer.render_line(6, 2, 3, 2);
+        assert_eq!(lit(&mut renderer), vec![(3, 2), (4, 2), (5, 2), (6, 2)]);
+    }
+
+    #[test]
+    fn line_is_clipped_to_the_framebuffer() {
+        let mut renderer = canvas(10, 10);
+        renderer.render_line(-2_000_000_000, 5, 2_000_000_000, 5);
+        assert_eq!(
+            lit(&mut renderer),
+            (0..10).map(|x| (x, 5)).collect::<Vec<_>>()
+        );
+
+        let mut renderer = canvas(10, 10);
+        renderer.render_line(i32::MIN, i32::MIN, i32::MAX, i32::MIN);
+        assert!(lit(&mut renderer).is_empty());
+    }
+
+    #[test]
+    fn circle_outline_is_symmetric() {
+        let mut renderer = canvas(21, 21);
+        renderer.render_circle(10, 10, 5, false);
+        let points = lit(&mut renderer);
+
+        for point in [(15, 10), (5, 10), (10, 5), (10, 15)] {
+            assert!(points.contains(&point), "{:?}", point);
+        }
+        assert!(!points.contains(&(10, 10)));
+        for &(x, y) in points.iter() {
+            assert!(points.contains(&(20 - x, y)) && points.contains(&(x, 20 - y)));
+        }
+    }
+
+    #[test]
+    fn filled_ellipse_covers_its_radii() {
+        let mut renderer = canvas(21, 21);
+        renderer.render_ellipse(10, 10, 6, 3, true);
+        let points = lit(&mut renderer);
+
+        for point in [(10, 10), (4, 10), (16, 10), (10, 7), (10, 13)] {
+            assert!(points.contains(&point), "{:?}", point);
+        }
+        for point in [(3, 10), (17, 10), (10, 6), (10, 14), (16, 13)] {
+            assert!(!points.contains(&point), "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn huge_ellipse_only_draws_visible_points() {
+        let mut renderer = canvas(10, 10);
+        renderer.render_ellipse(0, 0, i32::MAX, i32::MAX, false);
+        assert!(lit(&mut renderer).is_empty());
+
+        renderer.render_ellipse(0, 0, i32::MAX, i32::MAX, true);
+        assert_eq!(lit(&mut renderer).len(), 100);
+    }
+
+    #[test]
+    fn filled_box_covers_its_corners() {
+        let mut renderer = canvas(8, 8);
+        renderer.render_box(5, 4, 2, 2, true);
+        let expected: Vec<(i32, i32)> =
+            (2..=4).flat_map(|y| (2..=5).map(move |x| (x, y))).collect();
+        assert_eq!(lit(&mut renderer), expected);
+    }
+
+    #[test]
+    fn paint_stops_at_other_colors() {
+        let mut renderer = canvas(12, 12);
+        renderer.render_setcolor(255, 0, 0);
+        renderer.render_box(1, 1, 10, 10, false);
+        renderer.render_setcolor(255, 255, 255);
+        renderer.render_paint(5, 5);
+
+        let expected: Vec<(i32, i32)> =
+            (2..=9).flat_map(|y| (2..=9).map(move |x| (x, y))).collect();
+        assert_eq!(lit(&mut renderer), expected);
+
+        renderer.render_paint(5, 5);
+        assert_eq!(lit(&mut renderer), expected);
+    }
+
+    #[test]
+    fn huge_filled_box_only_walks_visible_rows() {
+        let mut renderer = canvas(10, 10);
+        renderer.render_box(0, -2_000_000_000, 3, 2_000_000_000, true);
+        let expected: Vec<(i32, i32)> =
+            (0..10).flat_map(|y| (0..=3).map(move |x| (x, y))).collect();
+        assert_eq!(lit(&mut renderer), expected);
+    }
+
+    #[test]
+    fn huge_filled_polygon_is_clipped() {
+        let mut renderer = canvas(10, 30);
+        renderer.render_polygon(&[(-2_000_000_000, 0), (2_000_000_000, 10), (0, 20)], true);
+        let points = lit(&mut renderer);
+        assert!(points.contains(&(5, 10)) && points.contains(&(0, 15)));
+        assert!(!points.contains(&(9, 25)));
     }
 }
@@ -0,0 +1,357 @@
+use crate::ast;
+use crate::checker;
+use crate::error::{ErrorCode, SbasicError, Span};
+use crate::formatter;
+use crate::interpreter;
+use crate::lexer;
+use crate::parser;
+use crate::token;
+
+use rustyline::error::ReadlineError;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+const PROMPT: &str = "> ";
+const DEFAULT_RENUM_START: u32 = 10;
+const DEFAULT_RENUM_STEP: u32 = 10;
+
+struct Session<'a> {
+    config: &'a interpreter::Config,
+    lines: BTreeMap<u32, String>,
+    globals: interpreter::Globals,
+}
+
+fn split_line_number(line: &str) -> Option<(u32, &str)> {
+    let digits = line
+        .find(|character: char| !character.is_ascii_digit())
+        .unwrap_or(line.len());
+    if digits == 0 {
+        return None;
+    }
+
+    let line_number = line[..digits].parse::<u32>().ok()?;
+    Some((line_number, line[digits..].trim()))
+}
+
+fn parse_range(argument: &str) -> Result<(u32, u32), SbasicError> {
+    let argument = argument.trim();
+    if argument.is_empty() {
+        return Ok((0, u32::MAX));
+    }
+
+    let parse_bound = |bound: &str, default: u32| match bound.trim() {
+        "" => Ok(default),
+        bound => bound.parse::<u32>().map_err(|_| {
+            SbasicError::parse(
+                ErrorCode::InvalidSyntax,
+                format!("Invalid line range {}.", argument),
+            )
+        }),
+    };
+
+    match argument.split_once('-') {
+        Some((start, end)) => Ok((parse_bound(start, 0)?, parse_bound(end, u32::MAX)?)),
+        None => {
+            let line_number = parse_bound(argument, 0)?;
+            Ok((line_number, line_number))
+        }
+    }
+}
+
+fn unquote(argument: &str) -> Result<&str, SbasicError> {
+    let argument = argument.trim();
+    let file_name = argument
+        .strip_prefix('"')
+        .map(|argument| argument.strip_suffix('"').unwrap_or(argument))
+        .unwrap_or(argument);
+
+    if file_name.is_empty() {
+        Err(SbasicError::parse(
+            ErrorCode::InvalidSyntax,
+            "Expected a file name.",
+        ))
+    } else {
+        Ok(file_name)
+    }
+}
+
+fn renumber_targets(line: &str, renumbered: &BTreeMap<u32, u32>) -> Result<String, SbasicError> {
+    let instruction = lexer::tokenize_line(line, lexer::IdentifierCase::default())?;
+    let mut characters: Vec<char> = line.chars().collect();
+
+    let targets: Vec<(usize, u32)> = instruction
+        .tokens
+        .windows(2)
+        .filter_map(|pair| match (&pair[0].1, &pair[1]) {
+            (jump, (position, token::Token::Number(target))) if jump.is_jump() => renumbered
+                .get(&(*target as u32))
+                .map(|&target| (*position as usize, target)),
+            _ => None,
+        })
+        .collect();
+
+    for (position, target) in targets.into_iter().rev() {
+        let end = characters[position..]
+            .iter()
+            .position(|character| !(character.is_ascii_digit() || *character == '.'))
+            .map(|length| position + length)
+            .unwrap_or(characters.len());
+        characters.splice(position..end, target.to_string().chars());
+    }
+
+    Ok(characters.into_iter().collect())
+}
+
+impl<'a> Session<'a> {
+    fn new(config: &'a interpreter::Config) -> Self {
+        Session {
+            config,
+            lines: BTreeMap::new(),
+            globals: interpreter::Globals::default(),
+        }
+    }
+
+    fn source_line(line_number: u32, body: &str) -> String {
+        format!("{} {}", line_number, body)
+    }
+
+    fn parse(&self) -> Result<ast::Program, SbasicError> {
+        let mut instructions: Vec<lexer::Instruction> = Vec::new();
+        for (line_number, body) in self.lines.iter() {
+            let line = Session::source_line(*line_number, body);
+            match lexer::tokenize_line(&line, self.config.identifier_case) {
+                Ok(instruction) => instructions.push(instruction),
+                Err(e) => return Err(e.at_line(*line_number)),
+            }
+        }
+
+        parser::parse_program(instructions)
+    }
+
+    fn store(&mut self, line_number: u32, body: &str) -> Result<(), SbasicError> {
+        if body.is_empty() {
+            self.lines.remove(&line_number);
+            return Ok(());
+        }
+
+        // Labels may belong to lines that are typed later, RUN reports the missing ones.
+        let mut instruction = lexer::tokenize_line(
+            &Session::source_line(line_number, body),
+            self.config.identifier_case,
+        )?;
+        parser::resolve_labels(&mut instruction, &|_| Some(line_number))?;
+        parser::parse_line(&instruction)?;
+        self.lines.insert(line_number, body.to_string());
+        Ok(())
+    }
+
+    fn list(&self, argument: &str) -> Result<(), SbasicError> {
+        let (start, end) = parse_range(argument)?;
+        for (line_number, body) in self.lines.range(start..=end) {
+            println!(
+                "{}",
+                formatter::format_line(&Session::source_line(*line_number, body))
+            );
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, argument: &str) -> Result<(), SbasicError> {
+        if argument.trim().is_empty() {
+            return Err(SbasicError::parse(
+                ErrorCode::InvalidSyntax,
+                "DELETE needs a line range, e.g. DELETE 10-50.",
+            ));
+        }
+
+        let (start, end) = parse_range(argument)?;
+        self.lines
+            .retain(|line_number, _| *line_number < start || *line_number > end);
+        Ok(())
+    }
+
+    fn renumber(&mut self, argument: &str) -> Result<(), SbasicError> {
+        let mut arguments = argument.split(',').map(|argument| argument.trim());
+        let mut next_argument = |default: u32| match arguments.next() {
+            None | Some("") => Ok(default),
+            Some(argument) => match argument.parse::<u32>() {
+                Ok(0) | Err(_) => Err(SbasicError::parse(
+                    ErrorCode::InvalidSyntax,
+                    format!("Invalid RENUM argument {}.", argument),
+                )),
+                Ok(argument) => Ok(argument),
+            },
+        };
+        let start = next_argument(DEFAULT_RENUM_START)?;
+        let step = next_argument(DEFAULT_RENUM_STEP)?;
+
+        let mut renumbered: BTreeMap<u32, u32> = BTreeMap::new();
+        let mut line_number = start;
+        for old_line_number in self.lines.keys() {
+            renumbered.insert(*old_line_number, line_number);
+            line_number = match line_number.checked_add(step) {
+                Some(line_number) => line_number,
+                None => {
+                    return Err(SbasicError::parse(
+                        ErrorCode::InvalidSyntax,
+                        "RENUM ran out of line numbers.",
+                    ))
+                }
+            };
+        }
+
+        let mut lines: BTreeMap<u32, String> = BTreeMap::new();
+        for (old_line_number, body) in self.lines.iter() {
+            let line = renumber_targets(&Session::source_line(0, body), &renumbered)?;
+            lines.insert(renumbered[old_line_number], line[2..].to_string());
+        }
+
+        self.lines = lines;
+        Ok(())
+    }
+
+    fn save(&self, argument: &str) -> Result<(), SbasicError> {
+        let source: String = self
+            .lines
+            .iter()
+            .map(|(line_number, body)| Session::source_line(*line_number, body) + "\n")
+            .collect();
+
+        fs::write(unquote(argument)?, source)?;
+        Ok(())
+    }
+
+    fn load(&mut self, argument: &str) -> Result<(), SbasicError> {
+        let source = fs::read_to_string(unquote(argument)?)?;
+
+        self.lines.clear();
+        self.globals = interpreter::Globals::default();
+        for line in source.lines().map(|line| line.trim()) {
+            if line.is_empty() {
+                continue;
+            }
+
+            let (line_number, body) = match split_line_number(line) {
+                Some(split) => split,
+                None => {
+                    let e = SbasicError::lex(
+                        ErrorCode::InvalidLineNumber,
+                        "Lines must start with a number.",
+                    )
+                    .with_span(Span::at(0));
+                    println!("{}", e.render(Some(line)));
+                    continue;
+                }
+            };
+
+            if let Err(e) = self.store(line_number, body) {
+                let source_line = Session::source_line(line_number, body);
+                println!("{}", e.render(Some(&source_line)));
+            }
+        }
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<(), SbasicError> {
+        let program = self.parse()?;
+        if let Some(error) = checker::check(&program).errors.into_iter().next() {
+            return Err(error);
+        }
+
+        self.globals = interpreter::Globals::default();
+        interpreter::run(&program, 0, self.config, &mut self.globals)
+    }
+
+    fn execute_immediate(&mut self, line: &str) -> Result<(), SbasicError> {
+        // While a block is still being typed the stored program cannot run, the line then runs on its own.
+        let mut program = match self.parse() {
+            Ok(program) if interpreter::Layout::new(&program).is_ok() => program,
+            _ => ast::Program { lines: Vec::new() },
+        };
+        let labels: HashMap<String, u32> = self
+            .lines
+            .iter()
+            .filter_map(|(line_number, body)| {
+                let instruction = lexer::tokenize_line(
+                    &Session::source_line(*line_number, body),
+                    self.config.identifier_case,
+                );
+                instruction
+                    .ok()?
+                    .label
+                    .map(|(_, label)| (label, *line_number))
+            })
+            .collect();
+
+        let mut instruction =
+            lexer::tokenize_line(&Session::source_line(0, line), self.config.identifier_case)?;
+        parser::resolve_labels(&mut instruction, &|label| labels.get(label).copied())?;
+        let immediate = parser::parse_line(&instruction)?;
+
+        // A GOTO from the prompt stops at the end of the program instead of running the prompt line again.
+        program.lines.push(ast::Line {
+            line_number: 0,
+            position: 0,
+            statement: ast::Stmt::End,
+        });
+        let start_index = program.lines.len();
+        program.lines.extend(immediate);
+
+        interpreter::run(&program, start_index, self.config, &mut self.globals)
+    }
+
+    fn evaluate(&mut self, line: &str) -> Result<(), SbasicError> {
+        if let Some((line_number, body)) = split_line_number(line) {
+            return self.store(line_number, body);
+        }
+
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        match command.to_uppercase().as_str() {
+            "LIST" => self.list(argument),
+            "RUN" => self.run(),
+            "NEW" => {
+                self.lines.clear();
+                self.globals = interpreter::Globals::default();
+                Ok(())
+            }
+            "DELETE" => self.delete(argument),
+            "RENUM" => self.renumber(argument),
+            "SAVE" => self.save(argument),
+            "LOAD" => self.load(argument),
+            _ => self.execute_immediate(line),
+        }
+    }
+}
+
+pub fn run(config: &interpreter::Config) -> Result<(), SbasicError> {
+    let mut editor = rustyline::DefaultEditor::new().map_err(|e| SbasicError::io(e.to_string()))?;
+    let mut session = Session::new(config);
+
+    println!("sBASIC, type RUN, LIST, NEW, DELETE, RENUM, SAVE or LOAD, Ctrl-D to quit.");
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => return Ok(()),
+            Err(e) => return Err(SbasicError::io(e.to_string())),
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+
+        if let Err(e) = session.evaluate(line) {
+            let source_line = e
+                .line_number()
+                .and_then(|line_number| {
+                    session
+                        .lines
+                        .get(&line_number)
+                        .map(|body| (line_number, body))
+                })
+                .map(|(line_number, body)| Session::source_line(line_number, body));
+            println!("{}", e.render(source_line.as_deref()));
+        }
+    }
+}
@@ -1,13 +1,37 @@
+use crate::error::{ErrorCode, SbasicError};
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Print,
     Input,
     Let,
+    Dim,
+    Option,
+    Base,
     Rem,
     If,
     Then,
+    Else,
+    ElseIf,
     Goto,
+    Def,
+    Sub,
+    Function,
+    Call,
+    Shared,
+    Exit,
+    Randomize,
+    Gosub,
+    Return,
     End,
+    For,
+    Next,
+    Step,
+    While,
+    Wend,
+    Do,
+    Loop,
+    Until,
 
     Screen,
     Clear,
@@ -16,16 +40,34 @@ pub enum Token {
     To,
     Dot,
     Circle,
+    Box,
+    Ellipse,
+    Polygon,
+    Paint,
+    Fill,
+    Flip,
+    Sleep,
+    Wait,
+    Frame,
+    SaveImage,
 
     Comment(String),
     Variable(String),
-    Number(i32),
+    Number(f64),
     Text(String),
 
     Plus,
     Minus,
     Divide,
     Multiply,
+    IntegerDivide,
+    Power,
+    Mod,
+
+    And,
+    Or,
+    Xor,
+    Not,
 
     Equals,
     NotEqual,
@@ -37,6 +79,7 @@ pub enum Token {
     Lparen,
     Rparen,
     Comma,
+    Colon,
 
     Bang,
     UnaryMinus,
@@ -50,15 +93,37 @@ pub enum Associativity {
 
 impl Token {
     pub fn to_token(symbol: &str) -> Option<Token> {
-        match symbol {
+        match symbol.to_ascii_uppercase().as_str() {
             "PRINT" => Some(Token::Print),
             "INPUT" => Some(Token::Input),
             "LET" => Some(Token::Let),
+            "DIM" => Some(Token::Dim),
+            "OPTION" => Some(Token::Option),
+            "BASE" => Some(Token::Base),
             "REM" => Some(Token::Rem),
             "IF" => Some(Token::If),
             "THEN" => Some(Token::Then),
+            "ELSE" => Some(Token::Else),
+            "ELSEIF" => Some(Token::ElseIf),
             "GOTO" => Some(Token::Goto),
+            "DEF" => Some(Token::Def),
+            "SUB" => Some(Token::Sub),
+            "FUNCTION" => Some(Token::Function),
+            "CALL" => Some(Token::Call),
+            "SHARED" => Some(Token::Shared),
+            "EXIT" => Some(Token::Exit),
+            "RANDOMIZE" => Some(Token::Randomize),
+            "GOSUB" => Some(Token::Gosub),
+            "RETURN" => Some(Token::Return),
             "END" => Some(Token::End),
+            "FOR" => Some(Token::For),
+            "NEXT" => Some(Token::Next),
+            "STEP" => Some(Token::Step),
+            "WHILE" => Some(Token::While),
+            "WEND" => Some(Token::Wend),
+            "DO" => Some(Token::Do),
+            "LOOP" => Some(Token::Loop),
+            "UNTIL" => Some(Token::Until),
 
             "SCREEN" => Some(Token::Screen),
             "CLEAR" => Some(Token::Clear),
@@ -67,11 +132,29 @@ impl Token {
             "TO" => Some(Token::To),
             "DOT" => Some(Token::Dot),
             "CIRCLE" => Some(Token::Circle),
+            "BOX" | "RECT" => Some(Token::Box),
+            "ELLIPSE" => Some(Token::Ellipse),
+            "POLYGON" => Some(Token::Polygon),
+            "PAINT" => Some(Token::Paint),
+            "FILL" => Some(Token::Fill),
+            "FLIP" | "PRESENT" => Some(Token::Flip),
+            "SLEEP" => Some(Token::Sleep),
+            "WAIT" => Some(Token::Wait),
+            "FRAME" => Some(Token::Frame),
+            "SAVEIMAGE" => Some(Token::SaveImage),
 
             "+" => Some(Token::Plus),
             "-" => Some(Token::Minus),
             "/" => Some(Token::Divide),
             "*" => Some(Token::Multiply),
+            "\\" => Some(Token::IntegerDivide),
+            "^" => Some(Token::Power),
+            "MOD" => Some(Token::Mod),
+
+            "AND" => Some(Token::And),
+            "OR" => Some(Token::Or),
+            "XOR" => Some(Token::Xor),
+            "NOT" => Some(Token::Not),
 
             "=" => Some(Token::Equals),
             "<>" => Some(Token::NotEqual),
@@ -83,6 +166,7 @@ impl Token {
             "(" => Some(Token::Lparen),
             ")" => Some(Token::Rparen),
             "," => Some(Token::Comma),
+            ":" => Some(Token::Colon),
             "!" => Some(Token::Bang),
 
             _ => None,
@@ -90,40 +174,44 @@ impl Token {
     }
 
     pub fn is_operator(&self) -> bool {
-        match *self {
+        matches!(
+            self,
             Token::Equals
-            | Token::NotEqual
-            | Token::LessThan
-            | Token::GreaterThan
-            | Token::LessThanEqual
-            | Token::GreaterThanEqual
-            | Token::Plus
-            | Token::Minus
-            | Token::Divide
-            | Token::Multiply
-            | Token::Bang
-            | Token::UnaryMinus => true,
-            _ => false,
-        }
+                | Token::NotEqual
+                | Token::LessThan
+                | Token::GreaterThan
+                | Token::LessThanEqual
+                | Token::GreaterThanEqual
+                | Token::Plus
+                | Token::Minus
+                | Token::Divide
+                | Token::Multiply
+                | Token::IntegerDivide
+                | Token::Power
+                | Token::Mod
+                | Token::And
+                | Token::Or
+                | Token::Xor
+                | Token::Not
+                | Token::Bang
+                | Token::UnaryMinus
+        )
     }
 
     pub fn is_comparison_operator(&self) -> bool {
-        match *self {
+        matches!(
+            self,
             Token::Equals
-            | Token::NotEqual
-            | Token::LessThan
-            | Token::GreaterThan
-            | Token::LessThanEqual
-            | Token::GreaterThanEqual => true,
-            _ => false,
-        }
+                | Token::NotEqual
+                | Token::LessThan
+                | Token::GreaterThan
+                | Token::LessThanEqual
+                | Token::GreaterThanEqual
+        )
     }
 
     pub fn is_unary_operator(&self) -> bool {
-        match *self {
-            Token::Bang | Token::UnaryMinus => true,
-            _ => false,
-        }
+        matches!(self, Token::Bang | Token::Not | Token::UnaryMinus)
     }
 
     pub fn is_binary_operator(&self) -> bool {
@@ -131,28 +219,48 @@ impl Token {
     }
 
     pub fn is_value(&self) -> bool {
-        match *self {
-            Token::Variable(_) | Token::Number(_) | Token::Text(_) => true,
-            _ => false,
-        }
+        matches!(self, Token::Variable(_) | Token::Number(_) | Token::Text(_))
+    }
+
+    pub fn is_keyword(&self) -> bool {
+        !(self.is_operator()
+            || self.is_value()
+            || matches!(
+                self,
+                Token::Comment(_) | Token::Lparen | Token::Rparen | Token::Comma | Token::Colon
+            ))
+    }
+
+    pub fn is_jump(&self) -> bool {
+        matches!(self, Token::Goto | Token::Gosub | Token::Then | Token::Else)
     }
 
-    pub fn operator_precedence(&self) -> Result<u8, String> {
+    pub fn operator_precedence(&self) -> Result<u8, SbasicError> {
         if !self.is_operator() {
-            return Err("ERR: Not an operator!".to_string());
+            return Err(SbasicError::parse(
+                ErrorCode::InvalidSyntax,
+                format!("{:?} is not an operator.", self),
+            ));
         }
 
         match *self {
-            Token::UnaryMinus | Token::Bang => Ok(1 << 5),
-            Token::Multiply | Token::Divide => Ok(1 << 4),
-            Token::Minus | Token::Plus => Ok(1 << 3),
-            _ => Ok(1 << 2),
+            Token::Power => Ok(11),
+            Token::UnaryMinus | Token::Bang => Ok(10),
+            Token::Multiply | Token::Divide => Ok(9),
+            Token::IntegerDivide => Ok(8),
+            Token::Mod => Ok(7),
+            Token::Minus | Token::Plus => Ok(6),
+            Token::Not => Ok(4),
+            Token::And => Ok(3),
+            Token::Or => Ok(2),
+            Token::Xor => Ok(1),
+            _ => Ok(5),
         }
     }
 
-    pub fn operator_associativity(&self) -> Result<Associativity, String> {
+    pub fn operator_associativity(&self) -> Result<Associativity, SbasicError> {
         match *self {
-            Token::UnaryMinus | Token::Bang => Ok(Associativity::Right),
+            Token::UnaryMinus | Token::Bang | Token::Not | Token::Power => Ok(Associativity::Right),
             _ => Ok(Associativity::Left),
         }
     }
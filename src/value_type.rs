@@ -1,16 +1,138 @@
+use crate::error::{ErrorCode, SbasicError};
+
 use std::cmp::{Ordering, PartialEq, PartialOrd};
-use std::ops::{Add, Div, Mul, Neg, Not, Sub};
+use std::fmt;
+use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Rem, Sub};
 use std::str::FromStr;
 
+// About 4 million elements, far beyond classic BASIC and well short of exhausting memory.
+pub const MAX_ARRAY_ELEMENTS: usize = 1 << 22;
+
 #[derive(Clone, Debug)]
 pub enum ValueType {
     Text(String),
-    Number(i32),
+    Number(f64),
     Bool(bool),
+    Array(Array),
+}
+
+#[derive(Clone, Debug)]
+pub struct Array {
+    pub base: usize,
+    pub bounds: Vec<usize>,
+    pub values: Vec<ValueType>,
+}
+
+impl Array {
+    pub fn new(base: usize, bounds: Vec<usize>, default: ValueType) -> Result<Array, SbasicError> {
+        let mut size = 1usize;
+
+        for bound in bounds.iter() {
+            if *bound < base {
+                return Err(SbasicError::runtime(
+                    ErrorCode::SubscriptOutOfRange,
+                    format!("Array bound {} is below OPTION BASE {}.", bound, base),
+                ));
+            }
+
+            let count = (bound - base).checked_add(1);
+            size = match count.and_then(|count| size.checked_mul(count)) {
+                Some(size) if size <= MAX_ARRAY_ELEMENTS => size,
+                _ => {
+                    return Err(SbasicError::runtime(
+                        ErrorCode::IllegalStatement,
+                        "Array is too large.",
+                    ))
+                }
+            };
+        }
+
+        Ok(Array {
+            base,
+            bounds,
+            values: vec![default; size],
+        })
+    }
+
+    fn offset(&self, subscripts: &[ValueType]) -> Result<usize, SbasicError> {
+        if subscripts.len() != self.bounds.len() {
+            return Err(SbasicError::runtime(
+                ErrorCode::ArgumentCount,
+                format!(
+                    "Array has {} dimension(s) but was given {} subscript(s).",
+                    self.bounds.len(),
+                    subscripts.len()
+                ),
+            ));
+        }
+
+        let mut offset = 0usize;
+        for (subscript, bound) in subscripts.iter().zip(self.bounds.iter()) {
+            let index = match subscript {
+                ValueType::Number(number) => number.round(),
+                _ => {
+                    return Err(SbasicError::type_mismatch(
+                        "Array subscripts must be numbers.",
+                    ))
+                }
+            };
+
+            if index < self.base as f64 || index > *bound as f64 {
+                return Err(SbasicError::runtime(
+                    ErrorCode::SubscriptOutOfRange,
+                    format!(
+                        "Subscript out of range: {} is not within {} to {}.",
+                        index, self.base, bound
+                    ),
+                ));
+            }
+
+            offset = offset * (bound - self.base + 1) + (index as usize - self.base);
+        }
+
+        Ok(offset)
+    }
+
+    pub fn get(&self, subscripts: &[ValueType]) -> Result<&ValueType, SbasicError> {
+        let offset = self.offset(subscripts)?;
+        Ok(&self.values[offset])
+    }
+
+    pub fn set(&mut self, subscripts: &[ValueType], value: ValueType) -> Result<(), SbasicError> {
+        let offset = self.offset(subscripts)?;
+        self.values[offset] = value;
+        Ok(())
+    }
+}
+
+fn format_number(number: f64) -> String {
+    // Round to 15 significant digits so results like 0.1 + 0.2 print as 0.3.
+    let rounded = f64::from_str(format!("{:.14e}", number).as_str()).unwrap_or(number);
+
+    if rounded != 0.0 && (rounded.abs() >= 1e16 || rounded.abs() < 1e-6) {
+        format!("{:E}", rounded)
+    } else {
+        format!("{}", rounded)
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueType::Text(text) => write!(f, "{}", text),
+            ValueType::Number(number) => write!(f, "{}", format_number(*number)),
+            ValueType::Bool(boolean) => write!(f, "{}", boolean),
+            ValueType::Array(array) => {
+                let bounds: Vec<String> =
+                    array.bounds.iter().map(|bound| bound.to_string()).collect();
+                write!(f, "ARRAY({})", bounds.join(", "))
+            }
+        }
+    }
 }
 
 impl Add for ValueType {
-    type Output = Result<ValueType, String>;
+    type Output = Result<ValueType, SbasicError>;
 
     fn add(self, other: ValueType) -> Self::Output {
         match (self, other) {
@@ -19,251 +141,284 @@ impl Add for ValueType {
                 Ok(ValueType::Text(format!("{}{}", rh, lh)))
             }
             (ValueType::Text(rh), ValueType::Number(lh)) => {
-                let number_string = i32::from_str(rh.as_str());
+                let number_string = f64::from_str(rh.as_str());
 
                 if let Result::Ok(number) = number_string {
                     Ok(ValueType::Number(number + lh))
                 } else {
-                    Err(format!("Cannot add string: {} to number: {}.", rh, lh))
+                    Err(SbasicError::type_mismatch(format!(
+                        "Cannot add string: {} to number: {}.",
+                        rh, lh
+                    )))
                 }
             }
             (ValueType::Number(rh), ValueType::Text(lh)) => {
-                let number_string = i32::from_str(lh.as_str());
+                let number_string = f64::from_str(lh.as_str());
 
                 if let Result::Ok(number) = number_string {
                     Ok(ValueType::Number(number + rh))
                 } else {
-                    Err(format!("Cannot add string: {} to number: {}.", lh, rh))
+                    Err(SbasicError::type_mismatch(format!(
+                        "Cannot add string: {} to number: {}.",
+                        lh, rh
+                    )))
                 }
             }
-            _ => Err(String::from("Can only add numbers and strings.")),
+            _ => Err(SbasicError::type_mismatch(
+                "Can only add numbers and strings.",
+            )),
         }
     }
 }
 
 impl Sub for ValueType {
-    type Output = Result<ValueType, String>;
+    type Output = Result<ValueType, SbasicError>;
 
     fn sub(self, other: ValueType) -> Self::Output {
         match (self, other) {
             (ValueType::Number(rh), ValueType::Number(lh)) => Ok(ValueType::Number(rh - lh)),
             (ValueType::Text(rh), ValueType::Number(lh)) => {
-                let number_string = i32::from_str(rh.as_str());
+                let number_string = f64::from_str(rh.as_str());
 
                 if let Result::Ok(number) = number_string {
                     Ok(ValueType::Number(number - lh))
                 } else {
-                    Err(format!("Cannot subtract string: {} to number: {}.", rh, lh))
+                    Err(SbasicError::type_mismatch(format!(
+                        "Cannot subtract string: {} to number: {}.",
+                        rh, lh
+                    )))
                 }
             }
             (ValueType::Number(rh), ValueType::Text(lh)) => {
-                let number_string = i32::from_str(lh.as_str());
+                let number_string = f64::from_str(lh.as_str());
 
                 if let Result::Ok(number) = number_string {
-                    Ok(ValueType::Number(number - rh))
+                    Ok(ValueType::Number(rh - number))
                 } else {
-                    Err(format!("Cannot subtract string: {} to number: {}.", lh, rh))
+                    Err(SbasicError::type_mismatch(format!(
+                        "Cannot subtract string: {} to number: {}.",
+                        lh, rh
+                    )))
                 }
             }
-            _ => Err(String::from("Can only subtract numbers.")),
+            _ => Err(SbasicError::type_mismatch("Can only subtract numbers.")),
         }
     }
 }
 
 impl Mul for ValueType {
-    type Output = Result<ValueType, String>;
+    type Output = Result<ValueType, SbasicError>;
 
     fn mul(self, other: ValueType) -> Self::Output {
         match (self, other) {
             (ValueType::Number(rh), ValueType::Number(lh)) => Ok(ValueType::Number(rh * lh)),
             (ValueType::Text(rh), ValueType::Number(lh)) => {
-                let number_string = i32::from_str(rh.as_str());
+                let number_string = f64::from_str(rh.as_str());
 
                 if let Result::Ok(number) = number_string {
                     Ok(ValueType::Number(number * lh))
                 } else {
-                    Err(format!("Cannot multiply string: {} to number: {}.", rh, lh))
+                    Err(SbasicError::type_mismatch(format!(
+                        "Cannot multiply string: {} to number: {}.",
+                        rh, lh
+                    )))
                 }
             }
             (ValueType::Number(rh), ValueType::Text(lh)) => {
-                let number_string = i32::from_str(lh.as_str());
+                let number_string = f64::from_str(lh.as_str());
 
                 if let Result::Ok(number) = number_string {
                     Ok(ValueType::Number(number * rh))
                 } else {
-                    Err(format!("Cannot multiply string: {} to number: {}.", lh, rh))
+                    Err(SbasicError::type_mismatch(format!(
+                        "Cannot multiply string: {} to number: {}.",
+                        lh, rh
+                    )))
                 }
             }
-            _ => Err(String::from("Can only multiply numbers.")),
+            _ => Err(SbasicError::type_mismatch("Can only multiply numbers.")),
         }
     }
 }
 
 impl Div for ValueType {
-    type Output = Result<ValueType, String>;
+    type Output = Result<ValueType, SbasicError>;
 
     fn div(self, other: ValueType) -> Self::Output {
         match (self, other) {
+            (ValueType::Number(_), ValueType::Number(0.0)) => Err(SbasicError::runtime(
+                ErrorCode::DivisionByZero,
+                "Division by zero.",
+            )),
             (ValueType::Number(rh), ValueType::Number(lh)) => Ok(ValueType::Number(rh / lh)),
             (ValueType::Text(rh), ValueType::Number(lh)) => {
-                let number_string = i32::from_str(rh.as_str());
+                let number_string = f64::from_str(rh.as_str());
 
                 if let Result::Ok(number) = number_string {
                     Ok(ValueType::Number(number / lh))
                 } else {
-                    Err(format!("Cannot divide string: {} to number: {}.", rh, lh))
+                    Err(SbasicError::type_mismatch(format!(
+                        "Cannot divide string: {} to number: {}.",
+                        rh, lh
+                    )))
                 }
             }
             (ValueType::Number(rh), ValueType::Text(lh)) => {
-                let number_string = i32::from_str(lh.as_str());
+                let number_string = f64::from_str(lh.as_str());
 
                 if let Result::Ok(number) = number_string {
-                    Ok(ValueType::Number(number / rh))
+                    Ok(ValueType::Number(rh / number))
                 } else {
-                    Err(format!("Cannot divide string: {} to number: {}.", lh, rh))
+                    Err(SbasicError::type_mismatch(format!(
+                        "Cannot divide string: {} to number: {}.",
+                        lh, rh
+                    )))
                 }
             }
-            _ => Err(String::from("Can only divide numbers.")),
+            _ => Err(SbasicError::type_mismatch("Can only divide numbers.")),
         }
     }
 }
 
-impl Not for ValueType {
-    type Output = Result<ValueType, String>;
+impl Rem for ValueType {
+    type Output = Result<ValueType, SbasicError>;
 
-    fn not(self) -> Self::Output {
-        match self {
-            ValueType::Bool(ref boolean) => Ok(ValueType::Bool(!boolean)),
-            _ => Err(String::from("Cannot apply NOT to non-boolean type.")),
+    fn rem(self, other: ValueType) -> Self::Output {
+        match (self, other) {
+            (ValueType::Number(_), ValueType::Number(0.0)) => Err(SbasicError::runtime(
+                ErrorCode::DivisionByZero,
+                "Division by zero.",
+            )),
+            (ValueType::Number(rh), ValueType::Number(lh)) => Ok(ValueType::Number(rh % lh)),
+            _ => Err(SbasicError::type_mismatch(
+                "MOD can only be used on numbers.",
+            )),
         }
     }
 }
 
-impl Neg for ValueType {
-    type Output = Result<ValueType, String>;
-
-    fn neg(self) -> Self::Output {
-        match self {
-            ValueType::Number(ref number) => Ok(ValueType::Number(-*number)),
-            _ => Err(String::from("Cannot apply NEG to non-boolean type.")),
+// Booleans give booleans, numbers are combined bit by bit on their integer part.
+fn logical(
+    operation: &str,
+    left: ValueType,
+    right: ValueType,
+    boolean: fn(bool, bool) -> bool,
+    bitwise: fn(i64, i64) -> i64,
+) -> Result<ValueType, SbasicError> {
+    match (left, right) {
+        (ValueType::Bool(rh), ValueType::Bool(lh)) => Ok(ValueType::Bool(boolean(rh, lh))),
+        (ValueType::Number(rh), ValueType::Number(lh)) => {
+            Ok(ValueType::Number(bitwise(rh as i64, lh as i64) as f64))
         }
+        (rh, lh) => Err(SbasicError::type_mismatch(format!(
+            "Cannot apply {} to {} and {}.",
+            operation, rh, lh
+        ))),
     }
 }
 
-impl PartialEq for ValueType {
-    fn eq(&self, other: &ValueType) -> bool {
-        match (self, other) {
-            (&ValueType::Text(ref rh), &ValueType::Text(ref lh)) => rh == lh,
-            (&ValueType::Number(rh), &ValueType::Number(lh)) => rh == lh,
-            (&ValueType::Bool(rh), &ValueType::Bool(lh)) => rh == lh,
-
-            (&ValueType::Number(rh), &ValueType::Text(ref lh)) => {
-                let number_string = i32::from_str(lh.as_str()).unwrap();
-                rh == number_string
-            }
+impl BitAnd for ValueType {
+    type Output = Result<ValueType, SbasicError>;
 
-            (&ValueType::Text(ref rh), &ValueType::Number(lh)) => {
-                let number_string = i32::from_str(rh.as_str()).unwrap();
-                lh == number_string
-            }
-            _ => false,
-        }
+    fn bitand(self, other: ValueType) -> Self::Output {
+        logical("AND", self, other, |rh, lh| rh && lh, |rh, lh| rh & lh)
     }
+}
 
-    fn ne(&self, other: &ValueType) -> bool {
-        match (self, other) {
-            (&ValueType::Number(rh), &ValueType::Number(lh)) => rh != lh,
-            (&ValueType::Text(ref rh), &ValueType::Text(ref lh)) => rh != lh,
-            (&ValueType::Bool(rh), &ValueType::Bool(lh)) => rh != lh,
+impl BitOr for ValueType {
+    type Output = Result<ValueType, SbasicError>;
 
-            (&ValueType::Number(rh), &ValueType::Text(ref lh)) => {
-                let number_string = i32::from_str(lh.as_str()).unwrap();
-                rh != number_string
-            }
+    fn bitor(self, other: ValueType) -> Self::Output {
+        logical("OR", self, other, |rh, lh| rh || lh, |rh, lh| rh | lh)
+    }
+}
 
-            (&ValueType::Text(ref rh), &ValueType::Number(lh)) => {
-                let number_string = i32::from_str(rh.as_str()).unwrap();
-                lh != number_string
-            }
-            _ => false,
-        }
+impl BitXor for ValueType {
+    type Output = Result<ValueType, SbasicError>;
+
+    fn bitxor(self, other: ValueType) -> Self::Output {
+        logical("XOR", self, other, |rh, lh| rh != lh, |rh, lh| rh ^ lh)
     }
 }
 
-impl PartialOrd for ValueType {
-    fn partial_cmp(&self, other: &ValueType) -> Option<Ordering> {
-        if self > other {
-            Some(Ordering::Greater)
-        } else if self < other {
-            Some(Ordering::Less)
-        } else if self == other {
-            Some(Ordering::Equal)
-        } else {
-            None
+impl ValueType {
+    pub fn integer_divide(self, other: ValueType) -> Result<ValueType, SbasicError> {
+        match (self / other)? {
+            ValueType::Number(quotient) => Ok(ValueType::Number(quotient.trunc())),
+            quotient => Ok(quotient),
         }
     }
 
-    fn gt(&self, other: &ValueType) -> bool {
+    pub fn power(self, other: ValueType) -> Result<ValueType, SbasicError> {
         match (self, other) {
-            (&ValueType::Number(rh), &ValueType::Number(lh)) => rh > lh,
-            (&ValueType::Number(rh), &ValueType::Text(ref lh)) => {
-                let number_string = i32::from_str(lh.as_str()).unwrap();
-                rh > number_string
-            }
-
-            (&ValueType::Text(ref rh), &ValueType::Number(lh)) => {
-                let number_string = i32::from_str(rh.as_str()).unwrap();
-                lh > number_string
-            }
-            _ => false,
+            (ValueType::Number(rh), ValueType::Number(lh)) => Ok(ValueType::Number(rh.powf(lh))),
+            _ => Err(SbasicError::type_mismatch("^ can only be used on numbers.")),
         }
     }
+}
 
-    fn ge(&self, other: &ValueType) -> bool {
-        match (self, other) {
-            (&ValueType::Number(rh), &ValueType::Number(lh)) => rh >= lh,
-            (&ValueType::Number(rh), &ValueType::Text(ref lh)) => {
-                let number_string = i32::from_str(lh.as_str()).unwrap();
-                rh >= number_string
-            }
+impl Not for ValueType {
+    type Output = Result<ValueType, SbasicError>;
 
-            (&ValueType::Text(ref rh), &ValueType::Number(lh)) => {
-                let number_string = i32::from_str(rh.as_str()).unwrap();
-                lh >= number_string
-            }
-            _ => false,
+    fn not(self) -> Self::Output {
+        match self {
+            ValueType::Bool(ref boolean) => Ok(ValueType::Bool(!boolean)),
+            ValueType::Number(number) => Ok(ValueType::Number(!(number as i64) as f64)),
+            _ => Err(SbasicError::type_mismatch(
+                "Cannot apply NOT to a string or an array.",
+            )),
         }
     }
+}
 
-    fn lt(&self, other: &ValueType) -> bool {
-        match (self, other) {
-            (&ValueType::Number(rh), &ValueType::Number(lh)) => rh < lh,
-            (&ValueType::Number(rh), &ValueType::Text(ref lh)) => {
-                let number_string = i32::from_str(lh.as_str()).unwrap();
-                rh < number_string
-            }
+impl Neg for ValueType {
+    type Output = Result<ValueType, SbasicError>;
 
-            (&ValueType::Text(ref rh), &ValueType::Number(lh)) => {
-                let number_string = i32::from_str(rh.as_str()).unwrap();
-                lh < number_string
-            }
-            _ => false,
+    fn neg(self) -> Self::Output {
+        match self {
+            ValueType::Number(ref number) => Ok(ValueType::Number(-*number)),
+            _ => Err(SbasicError::type_mismatch(
+                "Cannot apply NEG to non-number type.",
+            )),
         }
     }
+}
 
-    fn le(&self, other: &ValueType) -> bool {
-        match (self, other) {
-            (&ValueType::Number(rh), &ValueType::Number(lh)) => rh <= lh,
-            (&ValueType::Number(rh), &ValueType::Text(ref lh)) => {
-                let number_string = i32::from_str(lh.as_str()).unwrap();
-                rh <= number_string
-            }
+impl ValueType {
+    // Text next to a number is read as a number, other values of different types never compare.
+    // Text and booleans are only equal or unordered.
+    pub fn compare(&self, other: &ValueType) -> Result<Option<Ordering>, SbasicError> {
+        let number = |text: &str, number: f64| {
+            f64::from_str(text).map_err(|_| {
+                SbasicError::type_mismatch(format!(
+                    "Cannot compare string: {} to number: {}.",
+                    text,
+                    format_number(number)
+                ))
+            })
+        };
 
-            (&ValueType::Text(ref rh), &ValueType::Number(lh)) => {
-                let number_string = i32::from_str(rh.as_str()).unwrap();
-                lh <= number_string
+        match (self, other) {
+            (&ValueType::Number(rh), &ValueType::Number(lh)) => Ok(rh.partial_cmp(&lh)),
+            (&ValueType::Number(rh), ValueType::Text(lh)) => Ok(rh.partial_cmp(&number(lh, rh)?)),
+            (ValueType::Text(rh), &ValueType::Number(lh)) => Ok(number(rh, lh)?.partial_cmp(&lh)),
+            (ValueType::Text(rh), ValueType::Text(lh)) => Ok((rh == lh).then_some(Ordering::Equal)),
+            (&ValueType::Bool(rh), &ValueType::Bool(lh)) => {
+                Ok((rh == lh).then_some(Ordering::Equal))
             }
-            _ => false,
+            _ => Ok(None),
         }
     }
 }
+
+impl PartialEq for ValueType {
+    fn eq(&self, other: &ValueType) -> bool {
+        matches!(self.compare(other), Ok(Some(Ordering::Equal)))
+    }
+}
+
+impl PartialOrd for ValueType {
+    fn partial_cmp(&self, other: &ValueType) -> Option<Ordering> {
+        self.compare(other).ok().flatten()
+    }
+}